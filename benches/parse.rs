@@ -0,0 +1,81 @@
+//! Compares the allocating parse path with the buffer-reusing one
+#[macro_use]
+extern crate criterion;
+extern crate svmloader;
+
+use criterion::{black_box,Criterion};
+
+use svmloader::*;
+use svmloader::types::{parse_f32,SparseData};
+
+fn sample(rows: usize) -> String {
+    let mut s = String::new();
+    for i in 0..rows {
+        s.push_str(if i % 2 == 0 { "1 qid:" } else { "-1 qid:" });
+        s.push_str(&(i / 10).to_string());
+        for j in 0..40 {
+            s.push_str(&format!(" {}:{}", j * 25 + i % 25, (j as f32 + 1.0) / 8.0));
+        }
+        s.push_str(" # doc\n");
+    }
+    s
+}
+
+fn bench_lines(c: &mut Criterion) {
+    let data = sample(1000);
+    let sd = SparseData::new(1000);
+
+    c.bench_function("parse_line", |b| b.iter(|| {
+        for line in data.lines() {
+            black_box(parse_line(&BinaryClassification, &sd, line).unwrap());
+        }
+    }));
+
+    c.bench_function("parse_line_into", |b| {
+        let mut row = Row::default();
+        b.iter(|| {
+            for line in data.lines() {
+                parse_line_into(&BinaryClassification, &sd, line, &mut row).unwrap();
+                black_box(&row);
+            }
+        })
+    });
+}
+
+fn bench_reader(c: &mut Criterion) {
+    let data = sample(1000);
+
+    c.bench_function("reader_iter", |b| b.iter(|| {
+        for row in from_str(&data, BinaryClassification, SparseData::new(1000)) {
+            black_box(row.unwrap());
+        }
+    }));
+
+    c.bench_function("reader_read_into", |b| b.iter(|| {
+        let mut reader = from_str(&data, BinaryClassification, SparseData::new(1000));
+        let mut row = Row::default();
+        while let Some(res) = reader.read_into(&mut row) {
+            res.unwrap();
+            black_box(&row);
+        }
+    }));
+}
+
+fn bench_floats(c: &mut Criterion) {
+    let values: Vec<String> = (0..1000).map(|i| format!("{}", i as f32 / 16.0)).collect();
+
+    c.bench_function("std_f32", |b| b.iter(|| {
+        for v in &values {
+            black_box(v.parse::<f32>().unwrap());
+        }
+    }));
+
+    c.bench_function("parse_f32", |b| b.iter(|| {
+        for v in &values {
+            black_box(parse_f32(v).unwrap());
+        }
+    }));
+}
+
+criterion_group!(benches, bench_lines, bench_reader, bench_floats);
+criterion_main!(benches);
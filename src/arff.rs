@@ -0,0 +1,624 @@
+//! Weka ARFF files
+//!
+//! The header declares the attributes; one of them, by default the last, is
+//! the class.  Numeric and nominal attributes become features: the `j`th of
+//! them is handed to the `DataParse` as the token `j:value`, with a nominal
+//! value replaced by its position in the declaration and a missing `?` read
+//! as NaN.  String and date attributes are skipped.  A nominal class is
+//! passed to the `TargetReader` as the index of its value, so
+//! `DisjointClassification` yields class indices whose names are in
+//! `ArffHeader::class_names`; a numeric class is passed as written.
+//!
+//! Sparse instances `{3 1.5, 7 b}` only list non-zero attributes, so they
+//! suit `SparseData`; `DenseData` only sees the listed features.  A trailing
+//! `{weight}` sets the row weight.
+use std::borrow::Cow;
+use std::fmt::Write as FmtWrite;
+use std::io::{self,BufRead,Write};
+
+use compression::{self,Source};
+use error::{ErrorKind,ErrorPolicy,ParseError};
+use options::ParseOptions;
+use qid::QidReader;
+use types::{parse_f32,DataParse,Sparse};
+use writer::TargetWriter;
+use super::{Meta,Reader,Row,RowOf,TargetReader};
+
+/// The type of an attribute
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum AttributeKind {
+    /// `numeric`, `real` or `integer`
+    Numeric,
+    /// `{a,b,c}`: one of a fixed set of values
+    Nominal(Vec<String>),
+    String,
+    /// `date`, with its format if one was given
+    Date(Option<String>)
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub kind: AttributeKind
+}
+
+/// The `@relation` and `@attribute` declarations, and which is the class
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ArffHeader {
+    relation: String,
+    attributes: Vec<Attribute>,
+    class: usize,
+    class_set: bool
+}
+
+impl ArffHeader {
+    /// A header to write, without attributes yet
+    pub fn new<S: Into<String>>(relation: S) -> Self {
+        ArffHeader { relation: relation.into(), attributes: Vec::new(), class: 0, class_set: false }
+    }
+
+    /// Adds an attribute.  The last one added is the class unless `class`
+    /// says otherwise.
+    pub fn attribute<S: Into<String>>(mut self, name: S, kind: AttributeKind) -> Self {
+        self.attributes.push(Attribute { name: name.into(), kind });
+        if !self.class_set {
+            self.class = self.attributes.len() - 1;
+        }
+        self
+    }
+
+    /// Makes the attribute called `name` the class
+    pub fn class(mut self, name: &str) -> io::Result<Self> {
+        self.class = self.attributes.iter().position(|a| a.name == name)
+            .ok_or_else(|| invalid(format!("no attribute named {:?}", name)))?;
+        self.class_set = true;
+        Ok(self)
+    }
+
+    pub fn relation(&self) -> &str {
+        &self.relation
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Position of the class attribute
+    pub fn class_index(&self) -> usize {
+        self.class
+    }
+
+    /// The values of a nominal class, indexed by the target read
+    pub fn class_names(&self) -> Option<&[String]> {
+        match self.attributes.get(self.class).map(|a| &a.kind) {
+            Some(AttributeKind::Nominal(values)) => Some(values),
+            _ => None
+        }
+    }
+
+    fn is_feature(&self, i: usize) -> bool {
+        i != self.class && matches!(self.attributes[i].kind, AttributeKind::Numeric | AttributeKind::Nominal(_))
+    }
+
+    /// Names of the feature attributes, in feature order
+    pub fn feature_names(&self) -> Vec<&str> {
+        (0..self.attributes.len()).filter(|&i| self.is_feature(i))
+            .map(|i| self.attributes[i].name.as_str()).collect()
+    }
+
+    /// Reads declarations up to and including `@data`, returning the header
+    /// and the number of lines and bytes consumed
+    fn read<R: BufRead>(br: &mut R) -> io::Result<(ArffHeader, usize, u64)> {
+        let mut header = ArffHeader::new("");
+        let (mut lines, mut offset) = (0, 0);
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            let n = br.read_line(&mut buf)?;
+            if n == 0 { return Err(invalid("missing @data section".to_owned())) }
+            lines += 1;
+            offset += n as u64;
+            let line = buf.trim();
+            if line.is_empty() || line.starts_with('%') { continue }
+
+            let (keyword, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+            let rest = rest.trim();
+            match &keyword.to_ascii_lowercase()[..] {
+                "@relation" => header.relation = unquote(rest).0.into_owned(),
+                "@attribute" => {
+                    let (name, kind) = unquote(rest);
+                    let kind = parse_kind(kind.trim())
+                        .ok_or_else(|| invalid(format!("unsupported attribute type: {:?}", line)))?;
+                    header = header.attribute(name.into_owned(), kind);
+                },
+                "@data" => break,
+                _ => return Err(invalid(format!("unexpected header line: {:?}", line)))
+            }
+        }
+        if header.attributes.is_empty() {
+            return Err(invalid("no attributes declared".to_owned()))
+        }
+        Ok((header, lines, offset))
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_kind(t: &str) -> Option<AttributeKind> {
+    if let Some(values) = t.strip_prefix('{') {
+        let values = split_values(values.strip_suffix('}')?)?;
+        return Some(AttributeKind::Nominal(values.into_iter().map(|v| v.into_owned()).collect()))
+    }
+    let (word, rest) = t.split_at(t.find(char::is_whitespace).unwrap_or(t.len()));
+    match &word.to_ascii_lowercase()[..] {
+        "numeric" | "real" | "integer" => Some(AttributeKind::Numeric),
+        "string" => Some(AttributeKind::String),
+        "date" => {
+            let fmt = rest.trim();
+            Some(AttributeKind::Date(if fmt.is_empty() { None } else { Some(unquote(fmt).0.into_owned()) }))
+        },
+        _ => None
+    }
+}
+
+// Splits off a leading, possibly quoted, word and returns it with the rest
+fn unquote(s: &str) -> (Cow<'_, str>, &str) {
+    let s = s.trim_start();
+    let q = match s.chars().next() {
+        Some(q) if q == '\'' || q == '"' => q,
+        _ => {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            return (Cow::Borrowed(&s[..end]), &s[end..])
+        }
+    };
+    let mut word = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => if let Some((_, e)) = chars.next() { word.push(e) },
+            c if c == q => return (Cow::Owned(word), &s[i + 1..]),
+            c => word.push(c)
+        }
+    }
+    (Cow::Owned(word), "")
+}
+
+// Splits comma separated values, unquoting them.  None on a stray quote.
+fn split_values(s: &str) -> Option<Vec<Cow<'_, str>>> {
+    let mut out = Vec::new();
+    let mut rest = s;
+    loop {
+        let t = rest.trim_start();
+        if t.starts_with('\'') || t.starts_with('"') {
+            let (word, after) = unquote(t);
+            out.push(word);
+            let after = after.trim_start();
+            match after.strip_prefix(',') {
+                Some(r) => rest = r,
+                None if after.is_empty() => return Some(out),
+                None => return None
+            }
+        } else {
+            match t.find(',') {
+                Some(i) => {
+                    out.push(Cow::Borrowed(t[..i].trim()));
+                    rest = &t[i + 1..];
+                },
+                None => {
+                    out.push(Cow::Borrowed(t.trim()));
+                    return Some(out)
+                }
+            }
+        }
+    }
+}
+
+// Converts one value of attribute `a` to a number
+fn value(a: &Attribute, v: &str) -> Result<f32,ParseError> {
+    if v == "?" { return Ok(f32::NAN) }
+    match a.kind {
+        AttributeKind::Nominal(ref values) => values.iter().position(|x| x == v).map(|i| i as f32),
+        _ => parse_f32(v)
+    }.ok_or_else(|| ParseError::new(ErrorKind::BadValue, v))
+}
+
+struct Layout {
+    header: ArffHeader,
+    // Feature number of each attribute
+    features: Vec<Option<usize>>
+}
+
+impl Layout {
+    fn new(header: ArffHeader) -> Layout {
+        let mut j = 0;
+        let features = (0..header.attributes.len()).map(|i| {
+            if !header.is_feature(i) { return None }
+            j += 1;
+            Some(j - 1)
+        }).collect();
+        Layout { header, features }
+    }
+
+    // Comment lines give Ok(None)
+    fn parse<TR: TargetReader, P: DataParse, Q: QidReader>(&self, tr: &TR, p: &P, _: &ParseOptions<Q>, line: &str)
+        -> Result<Option<RowOf<TR, P, Q>>,ParseError>
+    {
+        let line = line.trim();
+        if line.starts_with('%') { return Ok(None) }
+        let bad_line = || ParseError::new(ErrorKind::BadValue, line);
+
+        // Split off a trailing {weight}
+        let (body, weight) = match line.rfind('{') {
+            Some(i) if i > 0 && line.ends_with('}') && line[..i].trim_end().ends_with(',') => {
+                let w = line[i + 1..line.len() - 1].trim();
+                let w = parse_f32(w).ok_or_else(|| ParseError::new(ErrorKind::BadWeight, w))?;
+                (line[..i].trim_end().trim_end_matches(',').trim_end(), Some(w))
+            },
+            _ => (line, None)
+        };
+
+        let attrs = &self.header.attributes;
+        let mut cells: Vec<(usize, Cow<'_, str>)> = Vec::new();
+        let sparse = body.starts_with('{');
+        if sparse {
+            let inner = body.strip_prefix('{').and_then(|b| b.strip_suffix('}')).ok_or_else(bad_line)?;
+            if !inner.trim().is_empty() {
+                for pair in split_values(inner).ok_or_else(bad_line)? {
+                    let pair = pair.trim();
+                    let (i, rest) = pair.split_at(pair.find(char::is_whitespace).unwrap_or(pair.len()));
+                    let i: usize = i.parse().ok().filter(|&i| i < attrs.len())
+                        .ok_or_else(|| ParseError::new(ErrorKind::BadIndex, pair))?;
+                    cells.push((i, unquote(rest).0.into_owned().into()));
+                }
+            }
+        } else {
+            let values = split_values(body).ok_or_else(bad_line)?;
+            if values.len() != attrs.len() {
+                return Err(ParseError::new(ErrorKind::ColumnCount, values.len().to_string()))
+            }
+            cells.extend(values.into_iter().enumerate());
+        }
+
+        let class = &attrs[self.header.class];
+        let mut target = None;
+        let mut buf = String::new();
+        for (i, v) in &cells {
+            if *i == self.header.class {
+                target = Some(match class.kind {
+                    AttributeKind::Nominal(_) if v != "?" => (value(class, v)? as usize).to_string(),
+                    _ => v.to_string()
+                });
+            } else if let Some(j) = self.features[*i] {
+                let x = value(&attrs[*i], v)?;
+                write!(buf, "{}:{} ", j, x).unwrap();
+            }
+        }
+        // An omitted sparse value is 0, the first value of a nominal class
+        let t = target.unwrap_or_else(|| "0".to_owned());
+        let y = tr.process(&t).ok_or_else(|| ParseError::new(ErrorKind::BadTarget, t))?;
+        let x = p.parse(buf.split_whitespace())?;
+        Ok(Some(Row { y, x, qid: None, comment: None, weight, meta: Meta::new() }))
+    }
+}
+
+/// Opens an ARFF file, decompressing it as `load` does
+pub fn load<TR: TargetReader, P: DataParse>(fname: &str, tr: TR, p: P) -> io::Result<ArffReader<TR, P>> {
+    ArffReader::new(compression::open(fname)?, tr, p)
+}
+
+/// Reads ARFF from any buffered source
+pub fn from_reader<TR: TargetReader, P: DataParse, R: BufRead>(br: R, tr: TR, p: P) -> io::Result<ArffReader<TR, P, R>> {
+    ArffReader::new(br, tr, p)
+}
+
+/// Reads ARFF from an in-memory string
+pub fn from_str<TR: TargetReader, P: DataParse>(data: &str, tr: TR, p: P) -> io::Result<ArffReader<TR, P, &[u8]>> {
+    ArffReader::new(data.as_bytes(), tr, p)
+}
+
+/// Streams the instances of an ARFF file.  Line numbers and the
+/// `ErrorPolicy` work as in `Reader`.
+pub struct ArffReader<TR: TargetReader, P: DataParse, R: BufRead = Source> {
+    inner: Reader<TR, P, R>,
+    layout: Layout
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead> ArffReader<TR, P, R> {
+    fn new(mut br: R, tr: TR, p: P) -> io::Result<Self> {
+        let (header, line, offset) = ArffHeader::read(&mut br)?;
+        let mut inner = super::from_reader(br, tr, p);
+        inner.line = line;
+        inner.offset = offset;
+        // Only `%` starts a comment, and `Layout::parse` skips those
+        inner.blank = |line| line.trim().is_empty();
+        Ok(ArffReader { inner, layout: Layout::new(header) })
+    }
+
+    pub fn header(&self) -> &ArffHeader {
+        &self.layout.header
+    }
+
+    /// Reads the attribute called `name` as the class instead of the last
+    pub fn class(mut self, name: &str) -> io::Result<Self> {
+        self.layout = Layout::new(self.layout.header.class(name)?);
+        Ok(self)
+    }
+
+    /// Sets how malformed lines are handled.  Defaults to `ErrorPolicy::Strict`.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.inner = self.inner.on_error(policy);
+        self
+    }
+
+    /// Number of malformed lines dropped so far
+    pub fn skipped(&self) -> usize {
+        self.inner.skipped()
+    }
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead> Iterator for ArffReader<TR, P, R> {
+    type Item = Result<Row<TR::Out, P::Out>,ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let layout = &self.layout;
+        loop {
+            match self.inner.read_next(|tr, p, opts, line| layout.parse(tr, p, opts, line))? {
+                Ok(Some(row)) => return Some(Ok(row)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The feature values of a row, for `ArffWriter`
+pub trait AttributeValues {
+    /// Calls `f` with each index and value, in index order
+    fn for_each_value<F: FnMut(usize, f32)>(&self, f: F);
+}
+
+impl AttributeValues for Vec<f32> {
+    fn for_each_value<F: FnMut(usize, f32)>(&self, mut f: F) {
+        for (i, &v) in self.iter().enumerate() {
+            f(i, v);
+        }
+    }
+}
+
+impl AttributeValues for Sparse {
+    fn for_each_value<F: FnMut(usize, f32)>(&self, mut f: F) {
+        for (&i, &v) in self.1.iter().zip(self.2.iter()) {
+            f(i, v);
+        }
+    }
+}
+
+fn quote(s: &str) -> Cow<'_, str> {
+    let plain = !s.is_empty() && s != "?" &&
+        !s.contains(|c: char| c.is_whitespace() || ",'\"%{}\\".contains(c));
+    if plain { return Cow::Borrowed(s) }
+    let mut q = String::from("'");
+    for c in s.chars() {
+        if c == '\'' || c == '\\' { q.push('\\'); }
+        q.push(c);
+    }
+    q.push('\'');
+    Cow::Owned(q)
+}
+
+/// Writes rows as ARFF.  The header is written on creation; feature `j` of
+/// a row goes to the `j`th non-class attribute, so the header should
+/// declare only numeric and nominal attributes besides the class.
+pub struct ArffWriter<TW: TargetWriter, W: Write> {
+    w: W,
+    tw: TW,
+    header: ArffHeader,
+    sparse: bool,
+    buf: String,
+    target: String
+}
+
+impl <TW: TargetWriter, W: Write> ArffWriter<TW, W> {
+    pub fn new(mut w: W, tw: TW, header: ArffHeader) -> io::Result<Self> {
+        if header.attributes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "an ARFF header needs at least the class attribute"))
+        }
+        let mut out = String::new();
+        writeln!(out, "@relation {}\n", quote(&header.relation)).unwrap();
+        for a in &header.attributes {
+            write!(out, "@attribute {} ", quote(&a.name)).unwrap();
+            match a.kind {
+                AttributeKind::Numeric => out.push_str("numeric"),
+                AttributeKind::String => out.push_str("string"),
+                AttributeKind::Date(None) => out.push_str("date"),
+                AttributeKind::Date(Some(ref f)) => write!(out, "date {}", quote(f)).unwrap(),
+                AttributeKind::Nominal(ref values) => {
+                    let values: Vec<_> = values.iter().map(|v| quote(v)).collect();
+                    write!(out, "{{{}}}", values.join(",")).unwrap();
+                }
+            }
+            out.push('\n');
+        }
+        out.push_str("\n@data\n");
+        w.write_all(out.as_bytes())?;
+        Ok(ArffWriter { w, tw, header, sparse: false, buf: String::new(), target: String::new() })
+    }
+
+    /// Writes `{index value, ...}` instances listing only non-zero values
+    pub fn sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    pub fn write_row<F: AttributeValues, Q>(&mut self, row: &Row<TW::In, F, Q>) -> io::Result<()> {
+        self.target.clear();
+        self.tw.write(&row.y, &mut self.target)?;
+        let (header, written, buf) = (&self.header, &self.target, &mut self.buf);
+        let class = header.class;
+        let target = match header.attributes[class].kind {
+            AttributeKind::Nominal(ref values) => {
+                let name = written.parse::<usize>().ok().and_then(|i| values.get(i));
+                quote(name.ok_or_else(|| invalid(format!("not a class index: {:?}", written)))?)
+            },
+            _ => quote(written)
+        };
+
+        // Feature j sits at attribute j, or j + 1 past the class
+        let n = header.attributes.len();
+        let attr = |j: usize| if j < class { j } else { j + 1 };
+        let mut values = Vec::new();
+        row.x.for_each_value(|j, v| values.push((attr(j), v)));
+        if values.last().is_some_and(|&(a, _)| a >= n) {
+            return Err(invalid("row has more features than the header has attributes".to_owned()))
+        }
+
+        let format = |a: usize, v: f32| -> io::Result<Cow<'_, str>> {
+            if v.is_nan() { return Ok(Cow::Borrowed("?")) }
+            match header.attributes[a].kind {
+                AttributeKind::Nominal(ref names) => names.get(v as usize).filter(|_| v >= 0.0 && v.fract() == 0.0)
+                    .map(|s| quote(s))
+                    .ok_or_else(|| invalid(format!("{} is not a value of {:?}", v, header.attributes[a].name))),
+                _ => Ok(Cow::Owned(v.to_string()))
+            }
+        };
+
+        buf.clear();
+        if self.sparse {
+            buf.push('{');
+            let mut first = true;
+            let mut class_done = false;
+            for (a, v) in values.into_iter().chain(Some((n, 0.0))) {
+                if !class_done && a > class {
+                    if !first { buf.push_str(", "); }
+                    write!(buf, "{} {}", class, target).unwrap();
+                    class_done = true;
+                    first = false;
+                }
+                if a == n || v == 0.0 { continue }
+                if !first { buf.push_str(", "); }
+                write!(buf, "{} {}", a, format(a, v)?).unwrap();
+                first = false;
+            }
+            buf.push('}');
+        } else {
+            let mut dense = vec![0.0; n];
+            for (a, v) in values {
+                dense[a] = v;
+            }
+            for (a, &v) in dense.iter().enumerate() {
+                if a > 0 { buf.push(','); }
+                if a == class {
+                    buf.push_str(&target);
+                } else {
+                    write!(buf, "{}", format(a, v)?).unwrap();
+                }
+            }
+        }
+        if let Some(w) = row.weight {
+            write!(buf, ", {{{}}}", w).unwrap();
+        }
+        buf.push('\n');
+        self.w.write_all(buf.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{DenseData,SparseData};
+    use super::super::{DisjointClassification,Regression};
+
+    const WEATHER: &str = "% weather
+@relation 'weather data'
+
+@attribute outlook {sunny, overcast, 'light rain'}
+@attribute temperature real
+@attribute id string
+@attribute play {no,yes}
+
+@data
+sunny,85,a1,no
+% a comment
+'light rain',?,a2,yes, {0.5}
+";
+
+    #[test]
+    fn read_dense() {
+        let reader = from_str(WEATHER, DisjointClassification, DenseData::new()).unwrap();
+        assert_eq!(reader.header().relation(), "weather data");
+        assert_eq!(reader.header().feature_names(), vec!["outlook", "temperature"]);
+        let names = reader.header().class_names().unwrap().to_vec();
+        let rows: Vec<_> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!((names[rows[0].y].as_str(), &rows[0].x), ("no", &vec![0.0, 85.0]));
+        assert_eq!((rows[1].y, rows[1].x[0], rows[1].weight), (1, 2.0, Some(0.5)));
+        assert!(rows[1].x[1].is_nan());
+
+        let reader = from_str(WEATHER, Regression, DenseData::new()).unwrap().class("temperature").unwrap();
+        let res: Vec<_> = reader.collect();
+        assert_eq!(res[0].as_ref().unwrap().x, vec![0.0, 0.0]);
+        let e = res[1].as_ref().err().unwrap();
+        assert_eq!((e.line, &e.token[..]), (12, "?"));
+    }
+
+    #[test]
+    fn hash_is_data() {
+        let data = "@RELATION r\n@ATTRIBUTE t STRING\n@ATTRIBUTE a NUMERIC\n@ATTRIBUTE c {x,y}\n@DATA\n#tag,1,x\n% note\n\nplain,2,y\n";
+        let ys: Vec<_> = from_str(data, DisjointClassification, SparseData::new(2)).unwrap()
+            .map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![0, 1]);
+    }
+
+    #[test]
+    fn read_sparse() {
+        let data = "@RELATION s\n@ATTRIBUTE a NUMERIC\n@ATTRIBUTE b NUMERIC\n@ATTRIBUTE c {x,y}\n@DATA\n{1 2.5, 2 y}\n{}\n{0 1}, {3}\n";
+        let rows: Vec<_> = from_str(data, DisjointClassification, SparseData::new(2)).unwrap()
+            .map(|r| r.unwrap()).collect();
+        assert_eq!((rows[0].y, &rows[0].x.1, &rows[0].x.2), (1, &vec![1], &vec![2.5]));
+        assert_eq!((rows[1].y, rows[1].x.1.len()), (0, 0));
+        assert_eq!((&rows[2].x.1, rows[2].weight), (&vec![0], Some(3.0)));
+        assert!(from_str("@attribute a numeric\n", Regression, DenseData::new()).is_err());
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let header = ArffHeader::new("out")
+            .attribute("f 1", AttributeKind::Numeric)
+            .attribute("class", AttributeKind::Nominal(vec!["neg".into(), "pos".into()]))
+            .attribute("f2", AttributeKind::Numeric)
+            .class("class").unwrap();
+        assert_eq!(header.clone().attribute("f3", AttributeKind::Numeric).class_index(), 1);
+        let e = ArffWriter::new(Vec::new(), DisjointClassification, ArffHeader::new("empty")).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        let mut w = ArffWriter::new(Vec::new(), DisjointClassification, header.clone()).unwrap();
+        w.write_row(&Row::new(1, vec![0.5, 2.0], None, None)).unwrap();
+        w.write_row(&Row::new(0, vec![f32::NAN, 0.0], None, None).with_weight(Some(2.0))).unwrap();
+        assert!(w.write_row(&Row::new(2, vec![], None, None)).is_err());
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.starts_with("@relation out\n\n@attribute 'f 1' numeric\n@attribute class {neg,pos}\n"));
+        assert!(out.ends_with("@data\n0.5,pos,2\n?,neg,0, {2}\n"));
+
+        let back: Vec<_> = from_str(&out, DisjointClassification, DenseData::new()).unwrap()
+            .class("class").unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!((back[0].y, &back[0].x), (1, &vec![0.5, 2.0]));
+        assert_eq!(back[1].weight, Some(2.0));
+
+        let mut w = ArffWriter::new(Vec::new(), DisjointClassification, header).unwrap().sparse(true);
+        w.write_row(&Row::new(1, Sparse(2, vec![1], vec![3.0]), None, None)).unwrap();
+        w.write_row(&Row::new(0, Sparse(2, vec![0], vec![1.0]), None, None)).unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.ends_with("@data\n{1 pos, 2 3}\n{0 1, 1 neg}\n"));
+        let back: Vec<_> = from_str(&out, DisjointClassification, SparseData::new(2)).unwrap()
+            .class("class").unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!((back[0].y, &back[0].x.1, back[1].y), (1, &vec![1], 0));
+    }
+}
@@ -0,0 +1,565 @@
+//! Binary cache of parsed rows
+//!
+//! Parsing text is slow next to reading the numbers back, so rows can be
+//! written once to a compact binary file and reopened directly.  The file
+//! is little-endian:
+//!
+//! ```text
+//! header   "SVMLCACH" version:u32 flags:u32 size:u64 mtime:u64 nanos:u32 0:u32 hash:u64 key:u64
+//! row      flags:u8 dims:u64 nnz:u32 target_len:u32 target
+//!          [qid:u64] [weight:f32] [comment_len:u32 comment]
+//!          indices:u32 × nnz  values:f32 × nnz
+//! footer   row offsets:u64 × rows  rows:u64 table_offset:u64
+//! ```
+//!
+//! Targets are stored as the text written by the `TargetWriter` and read
+//! back with the `TargetReader`.  Row metadata is not cached.  `Cache::open`
+//! reads the whole file into memory; with the `mmap` feature,
+//! `Cache::open_mapped` maps it instead.
+use std::fs::{self,File};
+use std::io::{self,BufWriter,Read,Seek,SeekFrom,Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use options::ParseOptions;
+use hashing::HashedSparseData;
+use types::{DataParse,Sparse,SparseData};
+use vocab::Vocabulary;
+use writer::TargetWriter;
+use super::{load,Row,TargetReader};
+
+const MAGIC: &[u8; 8] = b"SVMLCACH";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 56;
+const FOOTER_LEN: usize = 16;
+const HASH_SPAN: u64 = 64 * 1024;
+
+const HAS_QID: u8 = 1;
+const HAS_WEIGHT: u8 = 2;
+const HAS_COMMENT: u8 = 4;
+
+// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn invalid<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Identifies the version of a source file a cache was built from
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+    /// FNV-1a hash of the first and last 64 KiB
+    pub hash: u64
+}
+
+impl Fingerprint {
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let md = f.metadata()?;
+        let mtime = md.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let size = md.len();
+
+        let mut buf = Vec::new();
+        Read::by_ref(&mut f).take(HASH_SPAN).read_to_end(&mut buf)?;
+        if size > 2 * HASH_SPAN {
+            f.seek(SeekFrom::Start(size - HASH_SPAN))?;
+        }
+        f.read_to_end(&mut buf)?;
+        let hash = fnv1a(&buf);
+
+        Ok(Fingerprint { size, mtime_secs: mtime.as_secs(), mtime_nanos: mtime.subsec_nanos(), hash })
+    }
+
+    // The 32 bytes stored in cache and index headers
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(32);
+        b.extend_from_slice(&self.size.to_le_bytes());
+        b.extend_from_slice(&self.mtime_secs.to_le_bytes());
+        b.extend_from_slice(&self.mtime_nanos.to_le_bytes());
+        b.extend_from_slice(&0u32.to_le_bytes());
+        b.extend_from_slice(&self.hash.to_le_bytes());
+        b
+    }
+
+    pub(crate) fn decode(c: &mut Cursor) -> io::Result<Self> {
+        let size = c.u64()?;
+        let mtime_secs = c.u64()?;
+        let mtime_nanos = c.u32()?;
+        c.u32()?;
+        let hash = c.u64()?;
+        Ok(Fingerprint { size, mtime_secs, mtime_nanos, hash })
+    }
+}
+
+/// Writes rows in the cache format
+pub struct CacheWriter<TW: TargetWriter, W: Write> {
+    w: W,
+    tw: TW,
+    pos: u64,
+    offsets: Vec<u64>,
+    buf: String
+}
+
+impl <TW: TargetWriter, W: Write> CacheWriter<TW, W> {
+    /// Writes the header for a cache of the source described by
+    /// `fingerprint`, parsed in the way identified by `key`
+    pub fn new(w: W, tw: TW, fingerprint: Fingerprint, key: u64) -> io::Result<Self> {
+        let mut cw = CacheWriter { w, tw, pos: 0, offsets: Vec::new(), buf: String::new() };
+        cw.put(MAGIC)?;
+        cw.put(&VERSION.to_le_bytes())?;
+        cw.put(&0u32.to_le_bytes())?;
+        cw.put(&fingerprint.encode())?;
+        cw.put(&key.to_le_bytes())?;
+        Ok(cw)
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pos += bytes.len() as u64;
+        self.w.write_all(bytes)
+    }
+
+    fn put_len(&mut self, len: usize) -> io::Result<()> {
+        if len > u32::MAX as usize {
+            return Err(invalid(format!("length {} does not fit the cache format", len)))
+        }
+        self.put(&(len as u32).to_le_bytes())
+    }
+
+    /// Writes one row.  Indices must fit in a `u32`.
+    pub fn write_row(&mut self, row: &Row<TW::In, Sparse>) -> io::Result<()> {
+        let Sparse(dims, ref indices, ref values) = row.x;
+        if indices.len() != values.len() {
+            return Err(invalid("indices and values differ in length"))
+        }
+        if let Some(&i) = indices.iter().find(|&&i| i > u32::MAX as usize) {
+            return Err(invalid(format!("index {} does not fit the cache format", i)))
+        }
+        self.buf.clear();
+        self.tw.write(&row.y, &mut self.buf)?;
+        let target = ::std::mem::take(&mut self.buf);
+
+        self.offsets.push(self.pos);
+        let mut flags = 0;
+        if row.qid.is_some() { flags |= HAS_QID; }
+        if row.weight.is_some() { flags |= HAS_WEIGHT; }
+        if row.comment.is_some() { flags |= HAS_COMMENT; }
+        self.put(&[flags])?;
+        self.put(&(dims as u64).to_le_bytes())?;
+        self.put_len(indices.len())?;
+        self.put_len(target.len())?;
+        self.put(target.as_bytes())?;
+        self.buf = target;
+        if let Some(q) = row.qid {
+            self.put(&(q as u64).to_le_bytes())?;
+        }
+        if let Some(w) = row.weight {
+            self.put(&w.to_le_bytes())?;
+        }
+        if let Some(ref c) = row.comment {
+            self.put_len(c.len())?;
+            self.put(c.as_bytes())?;
+        }
+        for &i in indices {
+            self.put(&(i as u32).to_le_bytes())?;
+        }
+        for &v in values {
+            self.put(&v.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the row table and returns the sink
+    pub fn finish(mut self) -> io::Result<W> {
+        let table = self.pos;
+        let offsets = ::std::mem::take(&mut self.offsets);
+        for o in &offsets {
+            self.put(&o.to_le_bytes())?;
+        }
+        self.put(&(offsets.len() as u64).to_le_bytes())?;
+        self.put(&table.to_le_bytes())?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+// A whole file, mapped or read into memory
+enum Bytes {
+    #[cfg(feature = "mmap")]
+    Map(Mmap),
+    Vec(Vec<u8>)
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            #[cfg(feature = "mmap")]
+            Bytes::Map(ref m) => m,
+            Bytes::Vec(ref v) => v
+        }
+    }
+}
+
+// Bounds-checked little-endian decoding
+pub(crate) struct Cursor<'a> {
+    pub(crate) b: &'a [u8],
+    pub(crate) pos: usize
+}
+
+impl <'a> Cursor<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.b.len())
+            .ok_or_else(|| invalid("truncated cache"))?;
+        let s = &self.b[self.pos..end];
+        self.pos = end;
+        Ok(s)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        let mut a = [0; 4];
+        a.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(a))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        let mut a = [0; 8];
+        a.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(a))
+    }
+
+    pub(crate) fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub(crate) fn str(&mut self) -> io::Result<&'a str> {
+        let len = self.u32()? as usize;
+        ::std::str::from_utf8(self.take(len)?).map_err(|_| invalid("bad utf-8 in cache"))
+    }
+}
+
+/// An opened cache file
+pub struct Cache {
+    bytes: Bytes,
+    fingerprint: Fingerprint,
+    key: u64,
+    rows: usize,
+    table: usize
+}
+
+impl Cache {
+    /// Reads a cache file into memory
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Cache::from(Bytes::Vec(fs::read(path)?))
+    }
+
+    /// Like `open`, but decodes rows from a memory map of the file.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written, truncated or replaced in place while
+    /// the cache exists; doing so is undefined behaviour.  Replacing it by
+    /// renaming another file over it, as `load_cached` does, is fine.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mapped<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Cache::from(Bytes::Map(Mmap::map(&File::open(path)?)?))
+    }
+
+    /// Reads a cache held in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        Cache::from(Bytes::Vec(bytes))
+    }
+
+    fn from(bytes: Bytes) -> io::Result<Self> {
+        if bytes.len() < HEADER_LEN + FOOTER_LEN || &bytes[..8] != MAGIC {
+            return Err(invalid("not a row cache"))
+        }
+        let mut c = Cursor { b: &bytes, pos: 8 };
+        let version = c.u32()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported cache version {}", version)))
+        }
+        c.u32()?;
+        let fingerprint = Fingerprint::decode(&mut c)?;
+        let key = c.u64()?;
+
+        c.pos = bytes.len() - FOOTER_LEN;
+        let rows = c.u64()? as usize;
+        let table = c.u64()? as usize;
+        let fits = rows.checked_mul(8).and_then(|n| n.checked_add(table))
+            .is_some_and(|end| table >= HEADER_LEN && end == bytes.len() - FOOTER_LEN);
+        if !fits {
+            return Err(invalid("corrupt cache footer"))
+        }
+        Ok(Cache { bytes, fingerprint, key, rows, table })
+    }
+
+    /// The source the cache was built from
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// Identifies how the source was parsed; see `cache_key`
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Decodes the `i`th row, or `None` past the end
+    pub fn get<TR: TargetReader>(&self, i: usize, tr: &TR) -> Option<io::Result<Row<TR::Out, Sparse>>> {
+        if i >= self.rows {
+            return None
+        }
+        Some(self.decode(i, tr))
+    }
+
+    fn decode<TR: TargetReader>(&self, i: usize, tr: &TR) -> io::Result<Row<TR::Out, Sparse>> {
+        let mut c = Cursor { b: &self.bytes, pos: self.table + 8 * i };
+        let start = c.u64()? as usize;
+        c.b = &self.bytes[..self.table];
+        c.pos = start;
+
+        let flags = c.u8()?;
+        let dims = c.u64()? as usize;
+        let nnz = c.u32()? as usize;
+        let target = c.str()?;
+        let y = tr.process(target).ok_or_else(|| invalid(format!("bad cached target: {:?}", target)))?;
+        let qid = if flags & HAS_QID != 0 { Some(c.u64()? as usize) } else { None };
+        let weight = if flags & HAS_WEIGHT != 0 { Some(c.f32()?) } else { None };
+        let comment = if flags & HAS_COMMENT != 0 { Some(c.str()?.to_owned()) } else { None };
+
+        let raw = c.take(nnz.checked_mul(4).ok_or_else(|| invalid("truncated cache"))?)?;
+        let indices = raw.chunks(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).collect();
+        let raw = c.take(nnz * 4)?;
+        let values = raw.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        Ok(Row::new(y, Sparse(dims, indices, values), qid, comment).with_weight(weight))
+    }
+
+    /// Iterates over all rows, reading targets with `tr`
+    pub fn rows<TR: TargetReader>(self, tr: TR) -> CachedRows<TR> {
+        CachedRows { cache: self, tr, next: 0 }
+    }
+}
+
+/// Rows decoded from a `Cache`
+pub struct CachedRows<TR> {
+    cache: Cache,
+    tr: TR,
+    next: usize
+}
+
+impl <TR> CachedRows<TR> {
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+}
+
+impl <TR: TargetReader> Iterator for CachedRows<TR> {
+    type Item = io::Result<Row<TR::Out, Sparse>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.cache.get(self.next, &self.tr)?;
+        self.next += 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.cache.len() - self.next;
+        (n, Some(n))
+    }
+}
+
+/// Describes the settings that decide how a parser reads its input, leaving
+/// out what it counts or infers along the way
+pub trait ParserKey {
+    fn parser_key(&self) -> String;
+}
+
+impl <P: ParserKey + ?Sized> ParserKey for &P {
+    fn parser_key(&self) -> String {
+        (**self).parser_key()
+    }
+}
+
+impl ParserKey for SparseData {
+    fn parser_key(&self) -> String {
+        format!("SparseData {:?}", self.settings())
+    }
+}
+
+impl ParserKey for HashedSparseData {
+    fn parser_key(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl ParserKey for Vocabulary {
+    // The names read so far decide the indices, so they are part of the key
+    fn parser_key(&self) -> String {
+        format!("Vocabulary {:?} {} {:?}", self.oov_policy(), self.is_frozen(), self.names())
+    }
+}
+
+/// Hashes the target reader type, the parser's `ParserKey` and the options,
+/// plus a caller-chosen `tag` for anything else that changes the rows
+pub fn cache_key<TR, P: ParserKey>(p: &P, opts: &ParseOptions, tag: &str) -> u64 {
+    let desc = format!("{}|{}|{:?}|{}", ::std::any::type_name::<TR>(), p.parser_key(), opts, tag);
+    fnv1a(desc.as_bytes())
+}
+
+/// Reads `fname` through its cache, `<fname>.cache`.
+///
+/// The cache is used when the size, modification time and hash recorded in
+/// it match the source and its key matches `cache_key` of `p`, `opts` and
+/// `tag`; otherwise the source is parsed strictly and the cache rebuilt.
+/// The key is taken before parsing, so a growing `Vocabulary` is keyed by
+/// the names it held then and is not filled in when the cache is used.
+/// A parse error fails with `io::ErrorKind::InvalidData`.
+pub fn load_cached<TR, P>(fname: &str, tr: TR, p: P, opts: &ParseOptions, tag: &str) -> io::Result<CachedRows<TR>>
+    where TR: TargetReader + TargetWriter<In=<TR as TargetReader>::Out>,
+          P: DataParse<Out=Sparse> + ParserKey
+{
+    let path = format!("{}.cache", fname);
+    let fingerprint = Fingerprint::of(fname)?;
+    let key = cache_key::<TR, P>(&p, opts, tag);
+    if let Ok(cache) = Cache::open(&path) {
+        if cache.fingerprint() == fingerprint && cache.key() == key {
+            return Ok(cache.rows(tr))
+        }
+    }
+
+    let tmp = format!("{}.tmp", path);
+    let built = File::create(&tmp).and_then(|f| {
+        let mut cw = CacheWriter::new(BufWriter::new(f), &tr, fingerprint, key)?;
+        for row in load(fname, &tr, p)?.options(opts.clone()) {
+            let row = row.map_err(|e| invalid(e.to_string()))?;
+            cw.write_row(&row)?;
+        }
+        cw.finish().map(|_| ())
+    });
+    if let Err(e) = built {
+        let _ = fs::remove_file(&tmp);
+        return Err(e)
+    }
+    fs::rename(&tmp, &path)?;
+    Ok(Cache::open(&path)?.rows(tr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::TargetWeight;
+    use types::SparseData;
+    use super::super::{from_str,Regression};
+
+    #[test]
+    fn round_trip() {
+        let data = "1.5 qid:3 0:1 4:2.5 # first\n-2 7:1\n0\n";
+        let rows: Vec<_> = from_str(data, Regression, SparseData::new(8))
+            .map(|r| r.unwrap()).collect();
+        let mut cw = CacheWriter::new(Vec::new(), Regression, Fingerprint::default(), 7).unwrap();
+        for row in &rows {
+            cw.write_row(row).unwrap();
+        }
+        let weighted = Row::new(3.0, Sparse(8, vec![1], vec![0.5]), None, None).with_weight(Some(2.0));
+        cw.write_row(&weighted).unwrap();
+        let bytes = cw.finish().unwrap();
+
+        let cache = Cache::from_bytes(bytes.clone()).unwrap();
+        assert_eq!((cache.len(), cache.key()), (4, 7));
+        let back: Vec<_> = cache.rows(Regression).map(|r| r.unwrap()).collect();
+        for (a, b) in rows.iter().zip(&back) {
+            assert_eq!((a.y, a.x.0, &a.x.1, &a.x.2, a.qid, &a.comment, a.weight),
+                (b.y, b.x.0, &b.x.1, &b.x.2, b.qid, &b.comment, b.weight));
+        }
+        assert_eq!(back[3].weight, Some(2.0));
+
+        assert!(Cache::from_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+        let mut newer = bytes;
+        newer[8] = 3;
+        assert!(Cache::from_bytes(newer).is_err());
+    }
+
+    #[test]
+    fn rebuilds_when_source_changes() {
+        let path = ::std::env::temp_dir().join("svmloader_load_cached.svm");
+        let fname = path.to_str().unwrap();
+        let cache_path = format!("{}.cache", fname);
+        let _ = fs::remove_file(&cache_path);
+        let opts = ParseOptions::new();
+
+        fs::write(&path, "1 0:1\n2 1:1\n").unwrap();
+        let ys: Vec<f32> = load_cached(fname, Regression, SparseData::new(2), &opts, "").unwrap()
+            .map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![1.0, 2.0]);
+        let built = Cache::open(&cache_path).unwrap();
+        assert_eq!(built.fingerprint(), Fingerprint::of(fname).unwrap());
+        #[cfg(feature = "mmap")]
+        {
+            let mapped = unsafe { Cache::open_mapped(&cache_path).unwrap() };
+            assert_eq!(mapped.get(1, &Regression).unwrap().unwrap().y, 2.0);
+        }
+
+        let rows = load_cached(fname, Regression, SparseData::new(2), &opts, "").unwrap();
+        assert_eq!(rows.cache().len(), 2);
+
+        fs::write(&path, "3 0:1\n4 1:1\n5 0:2\n").unwrap();
+        let ys: Vec<f32> = load_cached(fname, Regression, SparseData::new(2), &opts, "").unwrap()
+            .map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![3.0, 4.0, 5.0]);
+
+        fs::write(&path, "3 0:1\n4 1:x\n").unwrap();
+        assert_eq!(load_cached(fname, Regression, SparseData::new(2), &opts, "").err().unwrap().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rebuilds_when_parsing_changes() {
+        let path = ::std::env::temp_dir().join("svmloader_load_cached_key.svm");
+        let fname = path.to_str().unwrap();
+        let _ = fs::remove_file(format!("{}.cache", fname));
+        fs::write(&path, "1:2 0:1 70:1\n").unwrap();
+
+        let weighted = ParseOptions::new().weights(TargetWeight::Suffix);
+        let wide = load_cached(fname, Regression, SparseData::new(100), &weighted, "").unwrap()
+            .next().unwrap().unwrap();
+        assert_eq!((wide.weight, wide.x.1), (Some(2.0), vec![0, 70]));
+
+        let narrow = load_cached(fname, Regression, SparseData::new(50), &weighted, "").unwrap()
+            .next().unwrap().unwrap();
+        assert_eq!((narrow.x.0, narrow.x.1), (50, vec![0]));
+
+        let p = SparseData::infer();
+        let key = cache_key::<Regression, _>(&p, &weighted, "");
+        from_str("1 0:1 9:1\n", Regression, &p).options(weighted.clone()).for_each(drop);
+        assert_eq!((p.dims(), cache_key::<Regression, _>(&p, &weighted, "")), (10, key));
+        let p = SparseData::new(2);
+        from_str("1 0:1 9:1\n", Regression, &p).for_each(drop);
+        assert_eq!((p.stats().out_of_range, &p), (1, &SparseData::new(2)));
+        assert_eq!(cache_key::<Regression, _>(&p, &weighted, ""), cache_key::<Regression, _>(&SparseData::new(2), &weighted, ""));
+
+        let rows = load_cached(fname, Regression, SparseData::new(50), &weighted, "v2").unwrap();
+        assert_ne!(rows.cache().key(), cache_key::<Regression, _>(&SparseData::new(50), &weighted, ""));
+        assert!(load_cached(fname, Regression, SparseData::new(50), &ParseOptions::new(), "").is_err());
+    }
+}
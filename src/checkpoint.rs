@@ -0,0 +1,110 @@
+//! Resuming a `Reader` where an earlier one stopped
+//!
+//! `Reader::checkpoint` records how far a reader has got.  Save it with
+//! the model, then pass it to `resume` after a restart to continue with
+//! the next line.  Plain files are seeked; compressed ones are decompressed
+//! and the bytes before the checkpoint discarded.
+use std::fs::File;
+use std::io::{self,BufRead,BufReader,Read,Seek,SeekFrom};
+
+use compression::{self,Compression,Source};
+use types::DataParse;
+use super::{from_reader,Reader,TargetReader};
+
+/// A position in the (decompressed) input of a `Reader`
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Checkpoint {
+    /// Byte offset of the next line to read
+    pub offset: u64,
+    /// Rows returned so far
+    pub row: usize,
+    /// Lines read so far, including blank and skipped ones
+    pub line: usize,
+    /// Malformed lines dropped so far
+    pub skipped: usize
+}
+
+/// Opens `fname` and continues reading at `cp`, which must come from a
+/// reader over the same file.  Line numbers in errors and later
+/// checkpoints carry on from `cp`.
+pub fn resume<TR: TargetReader, P: DataParse>(fname: &str, tr: TR, p: P, cp: Checkpoint) -> io::Result<Reader<TR,P>> {
+    let mut br = BufReader::new(File::open(fname)?);
+    let source: Source = if Compression::detect(br.fill_buf()?) == Compression::None {
+        if cp.offset > br.get_ref().metadata()?.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("checkpoint offset {} is past the end of {}", cp.offset, fname)))
+        }
+        br.seek(SeekFrom::Start(cp.offset))?;
+        Box::new(br)
+    } else {
+        skip(compression::decompress(br)?, cp.offset)?
+    };
+    Ok(at(from_reader(source, tr, p), cp))
+}
+
+/// Continues reading `br`, an unread source, at `cp` by discarding the
+/// bytes before it
+pub fn resume_reader<TR: TargetReader, P: DataParse, R: BufRead>(br: R, tr: TR, p: P, cp: Checkpoint) -> io::Result<Reader<TR,P,R>> {
+    Ok(at(from_reader(skip(br, cp.offset)?, tr, p), cp))
+}
+
+fn skip<R: BufRead>(mut br: R, n: u64) -> io::Result<R> {
+    let skipped = io::copy(&mut br.by_ref().take(n), &mut io::sink())?;
+    if skipped < n {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+            format!("checkpoint offset {} is past the end of the input", n)))
+    }
+    Ok(br)
+}
+
+fn at<TR: TargetReader, P: DataParse, R: BufRead>(mut reader: Reader<TR,P,R>, cp: Checkpoint) -> Reader<TR,P,R> {
+    reader.offset = cp.offset;
+    reader.rows = cp.row;
+    reader.line = cp.line;
+    reader.skipped = cp.skipped;
+    reader
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use types::SparseData;
+    use super::super::{from_str,load,ErrorPolicy,Regression};
+
+    #[test]
+    fn resume_after_checkpoint() {
+        let data = "1 0:1\n# note\n2 1:1\n3 0:2\n4 1:x\n";
+        let mut reader = from_str(data, Regression, SparseData::new(2));
+        reader.next();
+        reader.next();
+        let cp = reader.checkpoint();
+        assert_eq!(cp, Checkpoint { offset: 19, row: 2, line: 3, skipped: 0 });
+
+        let mut rest = resume_reader(data.as_bytes(), Regression, SparseData::new(2), cp).unwrap();
+        assert_eq!(rest.next().unwrap().unwrap().y, 3.0);
+        let e = rest.next().unwrap().err().unwrap();
+        assert_eq!((e.line, e.offset), (5, 25));
+        assert!(resume_reader(data.as_bytes(), Regression, SparseData::new(2),
+            Checkpoint { offset: 100, ..cp }).is_err());
+
+        let path = ::std::env::temp_dir().join("svmloader_checkpoint.svm");
+        let fname = path.to_str().unwrap();
+        fs::write(&path, data).unwrap();
+        let mut reader = load(fname, Regression, SparseData::new(2)).unwrap();
+        reader.next();
+        let mut rest = resume(fname, Regression, SparseData::new(2), reader.checkpoint()).unwrap();
+        assert_eq!(rest.next().unwrap().unwrap().y, 2.0);
+        assert_eq!(rest.checkpoint(), Checkpoint { offset: 19, row: 2, line: 3, skipped: 0 });
+
+        let data = "1 0:1\n2 1:x\n3 0:2\n4 1:1\n";
+        let mut reader = from_str(data, Regression, SparseData::new(2)).on_error(ErrorPolicy::Skip);
+        reader.next();
+        reader.next();
+        let cp = reader.checkpoint();
+        assert_eq!((cp.row, cp.skipped), (2, 1));
+        let rest = resume_reader(data.as_bytes(), Regression, SparseData::new(2), cp).unwrap()
+            .on_error(ErrorPolicy::Skip);
+        assert_eq!(rest.skipped(), 1);
+    }
+}
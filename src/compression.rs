@@ -0,0 +1,140 @@
+//! Transparent decompression of input files
+//!
+//! Each codec sits behind its own cargo feature (`gzip`, `zstd`, `bzip2`,
+//! `xz`).  Input is sniffed by its magic bytes, so the file extension does
+//! not matter.
+use std::fs::File;
+use std::io::{self,BufRead,BufReader};
+use std::path::Path;
+
+/// Boxed source returned by `open`
+pub type Source = Box<dyn BufRead + Send>;
+
+/// Compression formats recognised on input
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz
+}
+
+impl Compression {
+    /// Detects the format from the first bytes of a stream
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens a file, decompressing it if needed
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Source> {
+    let f = File::open(path)?;
+    decompress(BufReader::new(f))
+}
+
+/// Wraps a buffered source in the decoder matching its magic bytes
+pub fn decompress<R: BufRead + Send + 'static>(mut br: R) -> io::Result<Source> {
+    let format = Compression::detect(br.fill_buf()?);
+    match format {
+        Compression::None  => Ok(Box::new(br)),
+        Compression::Gzip  => gzip(br),
+        Compression::Zstd  => zstd(br),
+        Compression::Bzip2 => bzip2(br),
+        Compression::Xz    => xz(br)
+    }
+}
+
+#[cfg(not(all(feature = "gzip", feature = "zstd", feature = "bzip2", feature = "xz")))]
+fn unsupported(feature: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+        format!("input is compressed but svmloader was built without the `{}` feature", feature))
+}
+
+#[cfg(feature = "gzip")]
+fn gzip<R: BufRead + Send + 'static>(br: R) -> io::Result<Source> {
+    Ok(Box::new(BufReader::new(::flate2::bufread::MultiGzDecoder::new(br))))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip<R: BufRead + Send + 'static>(_br: R) -> io::Result<Source> {
+    Err(unsupported("gzip"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd<R: BufRead + Send + 'static>(br: R) -> io::Result<Source> {
+    Ok(Box::new(BufReader::new(::zstd::stream::read::Decoder::with_buffer(br)?)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd<R: BufRead + Send + 'static>(_br: R) -> io::Result<Source> {
+    Err(unsupported("zstd"))
+}
+
+#[cfg(feature = "bzip2")]
+fn bzip2<R: BufRead + Send + 'static>(br: R) -> io::Result<Source> {
+    Ok(Box::new(BufReader::new(::bzip2::bufread::MultiBzDecoder::new(br))))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bzip2<R: BufRead + Send + 'static>(_br: R) -> io::Result<Source> {
+    Err(unsupported("bzip2"))
+}
+
+#[cfg(feature = "xz")]
+fn xz<R: BufRead + Send + 'static>(br: R) -> io::Result<Source> {
+    Ok(Box::new(BufReader::new(::xz2::bufread::XzDecoder::new_multi_decoder(br))))
+}
+
+#[cfg(not(feature = "xz"))]
+fn xz<R: BufRead + Send + 'static>(_br: R) -> io::Result<Source> {
+    Err(unsupported("xz"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_magic() {
+        assert_eq!(Compression::detect(b"1 0:1"), Compression::None);
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 8]), Compression::Gzip);
+        assert_eq!(Compression::detect(b"BZh91AY"), Compression::Bzip2);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_roundtrip() {
+        use std::io::{Read,Write};
+        use flate2::write::GzEncoder;
+
+        let mut enc = GzEncoder::new(Vec::new(), ::flate2::Compression::default());
+        enc.write_all(b"1 0:1\n").unwrap();
+        let data = enc.finish().unwrap();
+
+        let mut out = String::new();
+        decompress(io::Cursor::new(data)).unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "1 0:1\n");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_roundtrip() {
+        use std::io::Read;
+        let data = ::zstd::encode_all(&b"1 0:1\n"[..], 0).unwrap();
+
+        let mut out = String::new();
+        decompress(io::Cursor::new(data)).unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "1 0:1\n");
+    }
+}
@@ -0,0 +1,225 @@
+//! In-memory datasets in compressed sparse row (CSR) form
+use std::iter::FromIterator;
+use std::ops::Range;
+
+use error::ParseError;
+use types::Sparse;
+use super::{Meta,Row};
+
+/// All rows of a sparse dataset packed into three flat arrays.
+///
+/// The features of row `i` are `indices[indptr[i]..indptr[i + 1]]` with the
+/// matching `values`; targets, qids, weights, meta tokens and comments are
+/// stored as parallel columns.
+#[derive(Debug,Clone)]
+pub struct SparseDataset<T, Q = usize> {
+    dims: usize,
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f32>,
+    targets: Vec<T>,
+    qids: Vec<Option<Q>>,
+    weights: Vec<Option<f32>>,
+    metas: Vec<Meta>,
+    comments: Vec<Option<String>>
+}
+
+/// A borrowed row of a `SparseDataset`
+#[derive(Debug,Clone,Copy)]
+pub struct RowView<'a, T: 'a, Q: 'a = usize> {
+    pub y: &'a T,
+    pub indices: &'a [usize],
+    pub values: &'a [f32],
+    pub qid: Option<&'a Q>,
+    pub weight: Option<f32>,
+    pub meta: &'a Meta,
+    pub comment: Option<&'a str>
+}
+
+impl <'a, T: 'a, Q: 'a> RowView<'a, T, Q> {
+    pub fn to_sparse(&self, dims: usize) -> Sparse {
+        Sparse(dims, self.indices.to_vec(), self.values.to_vec())
+    }
+}
+
+impl <T, Q> Default for SparseDataset<T, Q> {
+    fn default() -> Self {
+        SparseDataset {
+            dims: 0,
+            indptr: vec![0],
+            indices: Vec::new(),
+            values: Vec::new(),
+            targets: Vec::new(),
+            qids: Vec::new(),
+            weights: Vec::new(),
+            metas: Vec::new(),
+            comments: Vec::new()
+        }
+    }
+}
+
+impl <T, Q> SparseDataset<T, Q> {
+    pub fn new() -> Self {
+        SparseDataset::default()
+    }
+
+    /// Collects every row from a `Reader`, stopping at the first error
+    pub fn from_reader<I>(rows: I) -> Result<Self,ParseError>
+        where I: Iterator<Item=Result<Row<T, Sparse, Q>,ParseError>>
+    {
+        let mut ds = SparseDataset::new();
+        for row in rows {
+            ds.push(row?);
+        }
+        Ok(ds)
+    }
+
+    pub fn push(&mut self, row: Row<T, Sparse, Q>) {
+        let Sparse(dims, is, vs) = row.x;
+        self.dims = self.dims.max(dims);
+        self.indices.extend(is);
+        self.values.extend(vs);
+        self.indptr.push(self.indices.len());
+        self.targets.push(row.y);
+        self.qids.push(row.qid);
+        self.weights.push(row.weight);
+        self.metas.push(row.meta);
+        self.comments.push(row.comment);
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Feature dimension: the widest row seen
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// Number of stored (non-zero) features across all rows
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn indptr(&self) -> &[usize] { &self.indptr }
+    pub fn indices(&self) -> &[usize] { &self.indices }
+    pub fn values(&self) -> &[f32] { &self.values }
+    pub fn targets(&self) -> &[T] { &self.targets }
+    pub fn qids(&self) -> &[Option<Q>] { &self.qids }
+    pub fn weights(&self) -> &[Option<f32>] { &self.weights }
+    pub fn metas(&self) -> &[Meta] { &self.metas }
+
+    pub fn get(&self, i: usize) -> Option<RowView<'_, T, Q>> {
+        if i >= self.len() { return None }
+        let r = self.indptr[i]..self.indptr[i + 1];
+        Some(RowView {
+            y: &self.targets[i],
+            indices: &self.indices[r.clone()],
+            values: &self.values[r],
+            qid: self.qids[i].as_ref(),
+            weight: self.weights[i],
+            meta: &self.metas[i],
+            comment: self.comments[i].as_deref()
+        })
+    }
+
+    /// Panics if `i` is out of bounds
+    pub fn row(&self, i: usize) -> RowView<'_, T, Q> {
+        self.get(i).expect("row index out of bounds")
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, Q> {
+        Iter { ds: self, rows: 0..self.len() }
+    }
+
+    /// Copies rows `range` into a new dataset with the same dimension
+    pub fn slice(&self, range: Range<usize>) -> SparseDataset<T, Q> where T: Clone, Q: Clone {
+        let (lo, hi) = (self.indptr[range.start], self.indptr[range.end]);
+        SparseDataset {
+            dims: self.dims,
+            indptr: self.indptr[range.start..range.end + 1].iter().map(|p| p - lo).collect(),
+            indices: self.indices[lo..hi].to_vec(),
+            values: self.values[lo..hi].to_vec(),
+            targets: self.targets[range.clone()].to_vec(),
+            qids: self.qids[range.clone()].to_vec(),
+            weights: self.weights[range.clone()].to_vec(),
+            metas: self.metas[range.clone()].to_vec(),
+            comments: self.comments[range].to_vec()
+        }
+    }
+}
+
+impl <T, Q> FromIterator<Row<T, Sparse, Q>> for SparseDataset<T, Q> {
+    fn from_iter<I: IntoIterator<Item=Row<T, Sparse, Q>>>(rows: I) -> Self {
+        let mut ds = SparseDataset::new();
+        for row in rows {
+            ds.push(row);
+        }
+        ds
+    }
+}
+
+pub struct Iter<'a, T: 'a, Q: 'a = usize> {
+    ds: &'a SparseDataset<T, Q>,
+    rows: Range<usize>
+}
+
+impl <'a, T: 'a, Q: 'a> Iterator for Iter<'a, T, Q> {
+    type Item = RowView<'a, T, Q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next().map(|i| self.ds.row(i))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl <'a, T: 'a, Q: 'a> IntoIterator for &'a SparseDataset<T, Q> {
+    type Item = RowView<'a, T, Q>;
+    type IntoIter = Iter<'a, T, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::SparseData;
+    use super::super::{from_str,Regression};
+
+    #[test]
+    fn build_and_slice() {
+        let data = "1 qid:1 0:1 2:2\n2 qid:1 1:3 # x\n3 qid:2\n4 qid:2 2:4\n";
+        let ds = SparseDataset::from_reader(from_str(data, Regression, SparseData::new(3))).unwrap();
+        assert_eq!((ds.len(), ds.dims(), ds.nnz()), (4, 3, 4));
+        assert_eq!(ds.indptr(), &[0, 2, 3, 3, 4]);
+        assert_eq!(ds.row(1).comment, Some(" x"));
+        assert!(ds.row(2).indices.is_empty());
+        assert_eq!(ds.row(3).qid, Some(&2));
+
+        let s = ds.slice(1..3);
+        assert_eq!(s.indptr(), &[0, 1, 1]);
+        assert_eq!(s.targets(), &[2.0, 3.0]);
+        assert_eq!(s.row(0).values, &[3.0]);
+
+        let ys: Vec<f32> = ds.iter().map(|r| *r.y).collect();
+        assert_eq!(ys, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn inferred_width() {
+        let data = "1 0:1\n2 40:1\n3 2:1\n";
+        let ds = SparseDataset::from_reader(from_str(data, Regression, SparseData::infer())).unwrap();
+        assert_eq!(ds.dims(), 41);
+        assert_eq!(ds.indices(), &[0, 40, 2]);
+    }
+}
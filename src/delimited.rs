@@ -0,0 +1,396 @@
+//! CSV, TSV and other delimited text
+//!
+//! Each record is one line; fields may be wrapped in double quotes, with
+//! `""` standing for a quote inside them, but may not span lines.  One
+//! column holds the target and optional columns hold the qid and weight.
+//! Every other column, less any ignored ones, is a feature: the `j`th
+//! feature column is handed to the `DataParse` as the token `j:value`, so
+//! `DenseData` and a 0-based `SparseData` both fit.
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::io::{self,BufRead};
+
+use compression::{self,Source};
+use error::{ErrorKind,ErrorPolicy,ParseError};
+use options::ParseOptions;
+use qid::{NumericQid,QidReader};
+use types::{parse_f32,DataParse};
+use super::{Meta,Reader,Row,RowOf,TargetReader};
+
+/// A column picked by header name or by 0-based position
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Column {
+    Name(String),
+    Index(usize)
+}
+
+impl <'a> From<&'a str> for Column {
+    fn from(name: &'a str) -> Self {
+        Column::Name(name.to_owned())
+    }
+}
+
+impl From<usize> for Column {
+    fn from(i: usize) -> Self {
+        Column::Index(i)
+    }
+}
+
+/// What a missing feature value becomes.  A cell is missing when it is
+/// empty or one of the `na_values`.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum Missing {
+    /// Reject the row with `ErrorKind::BadValue`
+    #[default]
+    Error,
+    /// Zero, which sparse parsers leave out
+    Zero,
+    /// NaN
+    Nan,
+    /// A fixed value
+    Fill(f32)
+}
+
+/// How a delimited file is laid out
+#[derive(Debug,Clone)]
+pub struct CsvOptions {
+    delimiter: char,
+    header: bool,
+    target: Column,
+    qid: Option<Column>,
+    weight: Option<Column>,
+    ignore: Vec<Column>,
+    missing: Missing,
+    na_values: Vec<String>
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+            target: Column::Index(0),
+            qid: None,
+            weight: None,
+            ignore: Vec::new(),
+            missing: Missing::Error,
+            na_values: vec!["NA".to_owned(), "?".to_owned()]
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Comma separated, with a header, and the target in the first column
+    pub fn new() -> Self {
+        CsvOptions::default()
+    }
+
+    /// Tab separated, otherwise as `new`
+    pub fn tsv() -> Self {
+        CsvOptions::new().delimiter('\t')
+    }
+
+    pub fn delimiter(mut self, d: char) -> Self {
+        self.delimiter = d;
+        self
+    }
+
+    /// Whether the first line names the columns.  Defaults to true; without
+    /// a header columns can only be picked by position.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn target<C: Into<Column>>(mut self, c: C) -> Self {
+        self.target = c.into();
+        self
+    }
+
+    pub fn qid<C: Into<Column>>(mut self, c: C) -> Self {
+        self.qid = Some(c.into());
+        self
+    }
+
+    pub fn weight<C: Into<Column>>(mut self, c: C) -> Self {
+        self.weight = Some(c.into());
+        self
+    }
+
+    /// Leaves a column out of the features
+    pub fn ignore<C: Into<Column>>(mut self, c: C) -> Self {
+        self.ignore.push(c.into());
+        self
+    }
+
+    /// Sets what missing values become.  Defaults to `Missing::Error`.
+    pub fn missing(mut self, m: Missing) -> Self {
+        self.missing = m;
+        self
+    }
+
+    /// Sets the cell texts, besides an empty cell, read as missing.
+    /// Defaults to `NA` and `?`.
+    pub fn na_values<I, S>(mut self, values: I) -> Self
+        where I: IntoIterator<Item=S>, S: Into<String>
+    {
+        self.na_values = values.into_iter().map(|v| v.into()).collect();
+        self
+    }
+}
+
+// Column positions resolved against the header
+#[derive(Debug)]
+struct Layout {
+    delimiter: char,
+    columns: Option<usize>,
+    target: usize,
+    qid: Option<usize>,
+    weight: Option<usize>,
+    ignore: Vec<usize>,
+    missing: Missing,
+    na_values: Vec<String>
+}
+
+impl Layout {
+    fn new(opts: CsvOptions, header: Option<&[Cow<'_, str>]>) -> io::Result<Layout> {
+        let resolve = |c: &Column| match (c, header) {
+            (Column::Index(i), _) => Ok(*i),
+            (Column::Name(n), Some(h)) => h.iter().position(|c| c.trim() == n)
+                .ok_or_else(|| invalid(format!("no column named {:?}", n))),
+            (Column::Name(n), None) => Err(invalid(format!("column {:?} named, but there is no header", n)))
+        };
+        Ok(Layout {
+            delimiter: opts.delimiter,
+            columns: header.map(|h| h.len()),
+            target: resolve(&opts.target)?,
+            qid: opts.qid.as_ref().map(&resolve).transpose()?,
+            weight: opts.weight.as_ref().map(&resolve).transpose()?,
+            ignore: opts.ignore.iter().map(&resolve).collect::<io::Result<_>>()?,
+            missing: opts.missing,
+            na_values: opts.na_values
+        })
+    }
+
+    fn is_feature(&self, i: usize) -> bool {
+        i != self.target && Some(i) != self.qid && Some(i) != self.weight && !self.ignore.contains(&i)
+    }
+
+    fn parse<TR: TargetReader, P: DataParse, Q: QidReader>(&self, tr: &TR, p: &P, opts: &ParseOptions<Q>, line: &str)
+        -> Result<RowOf<TR, P, Q>,ParseError>
+    {
+        let cells = split(line, self.delimiter).ok_or_else(|| ParseError::new(ErrorKind::BadValue, line))?;
+        if self.columns.is_some_and(|n| n != cells.len()) {
+            return Err(ParseError::new(ErrorKind::ColumnCount, cells.len().to_string()))
+        }
+        let cell = |i: usize| cells.get(i).map(|c| c.trim())
+            .ok_or_else(|| ParseError::new(ErrorKind::ColumnCount, cells.len().to_string()));
+
+        let t = cell(self.target)?;
+        let y = tr.process(t).ok_or_else(|| ParseError::new(ErrorKind::BadTarget, t))?;
+        let qid = match self.qid.map(&cell).transpose()? {
+            Some(q) if !q.is_empty() => Some(opts.qids.process(q).ok_or_else(|| ParseError::new(ErrorKind::BadQid, q))?),
+            _ => None
+        };
+        let weight = match self.weight.map(&cell).transpose()? {
+            Some(w) if !w.is_empty() => Some(parse_f32(w).ok_or_else(|| ParseError::new(ErrorKind::BadWeight, w))?),
+            _ => None
+        };
+
+        let mut buf = String::new();
+        let features = cells.iter().enumerate().filter(|&(i, _)| self.is_feature(i));
+        for (j, (_, c)) in features.enumerate() {
+            let c = c.trim();
+            if c.is_empty() || self.na_values.iter().any(|na| na == c) {
+                match self.missing {
+                    Missing::Error => return Err(ParseError::new(ErrorKind::BadValue, c)),
+                    Missing::Zero => write!(buf, "{}:0 ", j).unwrap(),
+                    Missing::Nan => write!(buf, "{}:NaN ", j).unwrap(),
+                    Missing::Fill(v) => write!(buf, "{}:{} ", j, v).unwrap()
+                }
+            } else if parse_f32(c).is_some() {
+                write!(buf, "{}:{} ", j, c).unwrap();
+            } else {
+                return Err(ParseError::new(ErrorKind::BadValue, c))
+            }
+        }
+        let x = p.parse(buf.split_whitespace())?;
+        Ok(Row { y, x, qid, comment: None, weight, meta: Meta::new() })
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// Splits a record into fields, unquoting them.  Returns None on an
+// unterminated quote.
+fn split(line: &str, delimiter: char) -> Option<Vec<Cow<'_, str>>> {
+    let mut cells = Vec::new();
+    let mut rest = line;
+    loop {
+        // A tab or space delimiter is a field boundary, not padding
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != delimiter);
+        if let Some(quoted) = trimmed.strip_prefix('"') {
+            let mut cell = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (i, '"') if quoted[i + 1..].starts_with('"') => {
+                        cell.push('"');
+                        chars.next();
+                    },
+                    (i, '"') => break i + 1,
+                    (_, c) => cell.push(c)
+                }
+            };
+            cells.push(Cow::Owned(cell));
+            let after = &quoted[end..];
+            match after.find(delimiter) {
+                Some(i) => rest = &after[i + delimiter.len_utf8()..],
+                None => return Some(cells)
+            }
+        } else {
+            match rest.find(delimiter) {
+                Some(i) => {
+                    cells.push(Cow::Borrowed(&rest[..i]));
+                    rest = &rest[i + delimiter.len_utf8()..];
+                },
+                None => {
+                    cells.push(Cow::Borrowed(rest));
+                    return Some(cells)
+                }
+            }
+        }
+    }
+}
+
+/// Opens a delimited file, decompressing it as `load` does
+pub fn load<TR: TargetReader, P: DataParse>(fname: &str, tr: TR, p: P, opts: CsvOptions) -> io::Result<CsvReader<TR, P>> {
+    CsvReader::new(compression::open(fname)?, tr, p, opts)
+}
+
+/// Reads delimited rows from any buffered source
+pub fn from_reader<TR: TargetReader, P: DataParse, R: BufRead>(br: R, tr: TR, p: P, opts: CsvOptions) -> io::Result<CsvReader<TR, P, R>> {
+    CsvReader::new(br, tr, p, opts)
+}
+
+/// Reads delimited rows from an in-memory string
+pub fn from_str<TR: TargetReader, P: DataParse>(data: &str, tr: TR, p: P, opts: CsvOptions) -> io::Result<CsvReader<TR, P, &[u8]>> {
+    CsvReader::new(data.as_bytes(), tr, p, opts)
+}
+
+/// Streams rows from delimited text.  Only empty lines are skipped; a
+/// leading `#` is data.  Line numbers and the `ErrorPolicy` work as in
+/// `Reader`.
+pub struct CsvReader<TR: TargetReader, P: DataParse, R: BufRead = Source, Q: QidReader = NumericQid> {
+    inner: Reader<TR, P, R, Q>,
+    layout: Layout
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead> CsvReader<TR, P, R> {
+    fn new(mut br: R, tr: TR, p: P, opts: CsvOptions) -> io::Result<Self> {
+        let (mut line, mut offset) = (0, 0);
+        let layout = if opts.header {
+            let mut h = String::new();
+            offset = br.read_line(&mut h)? as u64;
+            line = 1;
+            let h = h.trim_start_matches('\u{feff}').trim_end_matches(['\n', '\r']);
+            let names = split(h, opts.delimiter).ok_or_else(|| invalid("unterminated quote in header".to_owned()))?;
+            Layout::new(opts, Some(&names))?
+        } else {
+            Layout::new(opts, None)?
+        };
+        let mut inner = super::from_reader(br, tr, p);
+        inner.line = line;
+        inner.offset = offset;
+        inner.blank = str::is_empty;
+        Ok(CsvReader { inner, layout })
+    }
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead, Q: QidReader> CsvReader<TR, P, R, Q> {
+    /// Sets how malformed lines are handled.  Defaults to `ErrorPolicy::Strict`.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.inner = self.inner.on_error(policy);
+        self
+    }
+
+    /// Sets how the qid column is read.  Defaults to `NumericQid`.
+    pub fn qids<Q2: QidReader>(self, qids: Q2) -> CsvReader<TR, P, R, Q2> {
+        CsvReader { inner: self.inner.options(ParseOptions::new().qids(qids)), layout: self.layout }
+    }
+
+    /// Number of malformed lines dropped so far
+    pub fn skipped(&self) -> usize {
+        self.inner.skipped()
+    }
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead, Q: QidReader> Iterator for CsvReader<TR, P, R, Q> {
+    type Item = Result<RowOf<TR, P, Q>,ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let layout = &self.layout;
+        self.inner.read_next(|tr, p, opts, line| layout.parse(tr, p, opts, line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qid::StringQid;
+    use types::{DenseData,SparseData};
+    use super::super::{BinaryClassification,Regression};
+
+    #[test]
+    fn split_quoted() {
+        let cells = split(r#"a, "b,""c""" ,,d"#, ',').unwrap();
+        assert_eq!(cells, vec!["a", "b,\"c\"", "", "d"]);
+        assert!(split("\"open", ',').is_none());
+        assert_eq!(split("1\t\t \"2\"", '\t').unwrap(), vec!["1", "", "2"]);
+    }
+
+    #[test]
+    fn named_columns() {
+        let data = "\u{feff}x1,label,q,w,x2\n0.5,1,a,2,3\n,-1,b,,4\n";
+        let opts = CsvOptions::new().target("label").qid("q").weight("w").missing(Missing::Nan);
+        let rows: Vec<_> = from_str(data, BinaryClassification, DenseData::new(), opts).unwrap()
+            .qids(StringQid).map(|r| r.unwrap()).collect();
+        assert_eq!((rows[0].y, rows[0].weight, &rows[0].x), (true, Some(2.0), &vec![0.5, 3.0]));
+        assert_eq!(rows[1].qid.as_deref(), Some("b"));
+        assert!(rows[1].x[0].is_nan() && rows[1].weight.is_none());
+
+        assert!(from_str(data, Regression, DenseData::new(), CsvOptions::new().target("y")).is_err());
+    }
+
+    #[test]
+    fn positional_tsv() {
+        let data = "1.5\tid7\t0\t2\n2\tid8\tNA\t0\n";
+        let opts = CsvOptions::tsv().header(false).ignore(1).missing(Missing::Zero);
+        let rows: Vec<_> = from_str(data, Regression, SparseData::new(2), opts).unwrap()
+            .map(|r| r.unwrap()).collect();
+        assert_eq!((&rows[0].x.1, &rows[0].x.2), (&vec![1], &vec![2.0]));
+        assert!(rows[1].x.1.is_empty());
+
+        let opts = CsvOptions::tsv().header(false).missing(Missing::Zero);
+        let row = from_str("1\t\t\"2\"\n", Regression, DenseData::new(), opts).unwrap().next().unwrap().unwrap();
+        assert_eq!(row.x, vec![0.0, 2.0]);
+
+        let res: Vec<_> = from_str("y,a\n1,2\n1,z\n1,2,3\n", Regression, DenseData::new(), CsvOptions::new())
+            .unwrap().collect();
+        let e = res[1].as_ref().err().unwrap();
+        assert_eq!((e.line, &e.token[..]), (3, "z"));
+        let rows: Vec<_> = from_str("y,a\n1,2\n1,z\n1,2,3\n1,NA\n", Regression, DenseData::new(), CsvOptions::new())
+            .unwrap().on_error(ErrorPolicy::Skip).collect();
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn hash_is_data() {
+        let opts = CsvOptions::new().target("label").ignore("id");
+        let rows: Vec<_> = from_str("id,label\n#7,1\n\nx8,0\n", Regression, DenseData::new(), opts).unwrap()
+            .map(|r| r.unwrap().y).collect();
+        assert_eq!(rows, vec![1.0, 0.0]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Errors raised while reading rows
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Why a line was rejected
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The target could not be read by the `TargetReader`
+    BadTarget,
+    /// A feature index was not a valid index
+    BadIndex,
+    /// A feature value was missing or not a number
+    BadValue,
+    /// A `qid:` token did not hold a valid query id
+    BadQid,
+    /// A row weight was not a number
+    BadWeight,
+    /// A feature index was at or past the fixed dimension
+    IndexOutOfRange,
+    /// A feature index appeared more than once in a row
+    DuplicateIndex,
+    /// A qid reappeared after rows of another qid
+    NonContiguousQid,
+    /// A feature name was not in a frozen vocabulary
+    UnknownFeature,
+    /// A delimited record had the wrong number of fields
+    ColumnCount,
+    /// The underlying source failed
+    Io(io::Error)
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::BadTarget => write!(f, "bad target"),
+            ErrorKind::BadIndex  => write!(f, "bad index"),
+            ErrorKind::BadValue  => write!(f, "bad value"),
+            ErrorKind::BadQid    => write!(f, "bad qid"),
+            ErrorKind::BadWeight => write!(f, "bad weight"),
+            ErrorKind::IndexOutOfRange => write!(f, "index out of range"),
+            ErrorKind::DuplicateIndex  => write!(f, "duplicate index"),
+            ErrorKind::NonContiguousQid => write!(f, "non-contiguous qid"),
+            ErrorKind::UnknownFeature => write!(f, "unknown feature"),
+            ErrorKind::ColumnCount => write!(f, "wrong number of columns"),
+            ErrorKind::Io(ref e) => write!(f, "io error: {}", e)
+        }
+    }
+}
+
+/// A rejected line.
+///
+/// `line` is 1-based and `offset` is the byte offset of the start of the
+/// line within the source.  Both are zero when the error comes straight
+/// from `parse_line`, which has no notion of position.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub offset: u64,
+    pub token: String,
+    pub kind: ErrorKind
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(kind: ErrorKind, token: S) -> Self {
+        ParseError {
+            line: 0,
+            offset: 0,
+            token: token.into(),
+            kind
+        }
+    }
+
+    /// Sets the position of the error within its source
+    pub fn at(mut self, line: usize, offset: u64) -> Self {
+        self.line = line;
+        self.offset = offset;
+        self
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::new(ErrorKind::Io(e), "")
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Line 0 means the position is unknown, or only the byte offset is
+        match (self.line, self.offset) {
+            (0, 0) => (),
+            (0, offset) => write!(f, "byte {}: ", offset)?,
+            (line, offset) => write!(f, "line {} (byte {}): ", line, offset)?
+        }
+        write!(f, "{}", self.kind)?;
+        if !self.token.is_empty() {
+            write!(f, " `{}`", self.token)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ErrorKind::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// Called with the offending line and its error
+pub type ErrorHandler = Box<dyn FnMut(&str, &ParseError) + Send>;
+
+/// What a `Reader` does with a malformed line
+#[derive(Default)]
+pub enum ErrorPolicy {
+    /// Yield the error and stop reading
+    #[default]
+    Strict,
+    /// Drop the line, counting it in `Reader::skipped`
+    Skip,
+    /// Hand the line and its error to the callback, then drop it as `Skip` does
+    Callback(ErrorHandler)
+}
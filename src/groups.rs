@@ -0,0 +1,200 @@
+//! Grouping rows by query id for learning-to-rank
+use std::collections::{HashMap,HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::vec;
+
+use error::{ErrorKind,ParseError};
+use super::Row;
+
+/// A query id and its rows, in file order.  Rows without a `qid:` token
+/// form groups with a `None` id.
+pub type Group<T, F, Q = usize> = (Option<Q>, Vec<Row<T, F, Q>>);
+
+// Finds the line and byte offset of the last row read from a source
+type Position<I> = fn(&I) -> (usize, u64);
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Mode {
+    Consecutive,
+    Verify,
+    Regroup
+}
+
+/// Yields runs of consecutive rows sharing a qid.
+///
+/// By default a qid that reappears later simply starts a new group.
+/// `verify_contiguous` turns that into a `NonContiguousQid` error, and
+/// `regroup` instead reads the whole input and gathers every row of a qid
+/// into one group, ordered by first appearance.  Any error ends iteration;
+/// rows of the group read before it are yielded first.  Over a `Reader`,
+/// a `NonContiguousQid` error gives the line of the offending row.
+pub struct QueryGroups<I, T, F, Q> {
+    rows: I,
+    mode: Mode,
+    next_row: Option<Row<T, F, Q>>,
+    seen: HashSet<Option<Q>>,
+    regrouped: Option<vec::IntoIter<Group<T, F, Q>>>,
+    position: Option<Position<I>>,
+    pending: Option<ParseError>,
+    done: bool
+}
+
+impl <I, T, F, Q> QueryGroups<I, T, F, Q>
+    where I: Iterator<Item=Result<Row<T, F, Q>,ParseError>>,
+          Q: Clone + Hash + Eq + Debug
+{
+    pub fn new(rows: I) -> Self {
+        QueryGroups {
+            rows,
+            mode: Mode::Consecutive,
+            next_row: None,
+            seen: HashSet::new(),
+            regrouped: None,
+            position: None,
+            pending: None,
+            done: false
+        }
+    }
+
+    pub(crate) fn with_position(mut self, position: Position<I>) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    /// Fails with `NonContiguousQid` if a qid shows up again after its group
+    pub fn verify_contiguous(mut self) -> Self {
+        self.mode = Mode::Verify;
+        self
+    }
+
+    /// Reads all rows into memory and groups them regardless of order
+    pub fn regroup(mut self) -> Self {
+        self.mode = Mode::Regroup;
+        self
+    }
+
+    fn fail(&mut self, e: ParseError) -> Option<Result<Group<T, F, Q>,ParseError>> {
+        self.done = true;
+        Some(Err(e))
+    }
+
+    fn regroup_all(&mut self) -> Result<vec::IntoIter<Group<T, F, Q>>,ParseError> {
+        let mut groups: Vec<Group<T, F, Q>> = Vec::new();
+        let mut index = HashMap::new();
+        for row in &mut self.rows {
+            let row = row?;
+            let i = *index.entry(row.qid.clone()).or_insert_with(|| {
+                groups.push((row.qid.clone(), Vec::new()));
+                groups.len() - 1
+            });
+            groups[i].1.push(row);
+        }
+        Ok(groups.into_iter())
+    }
+}
+
+impl <I, T, F, Q> Iterator for QueryGroups<I, T, F, Q>
+    where I: Iterator<Item=Result<Row<T, F, Q>,ParseError>>,
+          Q: Clone + Hash + Eq + Debug
+{
+    type Item = Result<Group<T, F, Q>,ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.pending.take() {
+            return self.fail(e)
+        }
+        if self.done { return None }
+        if self.mode == Mode::Regroup {
+            if self.regrouped.is_none() {
+                match self.regroup_all() {
+                    Ok(groups) => self.regrouped = Some(groups),
+                    Err(e) => return self.fail(e)
+                }
+            }
+            return self.regrouped.as_mut().unwrap().next().map(Ok)
+        }
+
+        let first = match self.next_row.take() {
+            Some(row) => row,
+            None => match self.rows.next()? {
+                Ok(row) => row,
+                Err(e) => return self.fail(e)
+            }
+        };
+        let qid = first.qid.clone();
+        if self.mode == Mode::Verify && !self.seen.insert(qid.clone()) {
+            // `first` is always the last row read from `rows`
+            let mut e = ParseError::new(ErrorKind::NonContiguousQid, format!("{:?}", qid));
+            if let Some(position) = self.position {
+                let (line, offset) = position(&self.rows);
+                e = e.at(line, offset);
+            }
+            return self.fail(e)
+        }
+
+        let mut group = vec![first];
+        for row in &mut self.rows {
+            match row {
+                Ok(row) if row.qid == qid => group.push(row),
+                Ok(row) => {
+                    self.next_row = Some(row);
+                    break
+                },
+                Err(e) => {
+                    self.pending = Some(e);
+                    break
+                }
+            }
+        }
+        Some(Ok((qid, group)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{Sparse,SparseData};
+    use super::super::{from_str,Regression};
+
+    const DATA: &str = "1 qid:1 0:1\n2 qid:1 0:1\n3 qid:2 0:1\n4 qid:1 0:1\n5 0:1\n";
+
+    fn summary(groups: Vec<Group<f32, Sparse>>) -> Vec<(Option<usize>, Vec<f32>)> {
+        groups.into_iter().map(|(q, rows)| (q, rows.iter().map(|r| r.y).collect())).collect()
+    }
+
+    #[test]
+    fn consecutive_groups() {
+        let groups = from_str(DATA, Regression, SparseData::new(1)).query_groups()
+            .collect::<Result<Vec<_>,_>>().unwrap();
+        assert_eq!(summary(groups), vec![
+            (Some(1), vec![1.0, 2.0]), (Some(2), vec![3.0]), (Some(1), vec![4.0]), (None, vec![5.0])
+        ]);
+    }
+
+    #[test]
+    fn verify_and_regroup() {
+        let res: Vec<_> = from_str(DATA, Regression, SparseData::new(1)).query_groups()
+            .verify_contiguous().collect();
+        assert_eq!(res.len(), 3);
+        let e = res[2].as_ref().err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::NonContiguousQid));
+        assert_eq!((e.line, e.offset), (4, 36));
+        assert_eq!(e.to_string(), "line 4 (byte 36): non-contiguous qid `Some(1)`");
+
+        let groups = from_str(DATA, Regression, SparseData::new(1)).query_groups().regroup()
+            .collect::<Result<Vec<_>,_>>().unwrap();
+        assert_eq!(summary(groups), vec![
+            (Some(1), vec![1.0, 2.0, 4.0]), (Some(2), vec![3.0]), (None, vec![5.0])
+        ]);
+    }
+
+    #[test]
+    fn error_ends_group() {
+        let data = "1 qid:1 0:1\n2 qid:1 0:1\n3 qid:1 x\n4 qid:2 0:1\n";
+        let res: Vec<_> = from_str(data, Regression, SparseData::new(1)).query_groups().collect();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].as_ref().unwrap().1.len(), 2);
+        assert_eq!(res[1].as_ref().err().unwrap().line, 3);
+    }
+}
@@ -0,0 +1,148 @@
+//! Feature hashing for rows with named features
+use error::ParseError;
+use types::{fill_summed,named_feature,DataParse,Sparse};
+
+/// MurmurHash3, x86 32-bit variant, of `key` with `seed`
+pub fn murmur3_32(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mix = |mut k: u32| {
+        k = k.wrapping_mul(C1);
+        k = k.rotate_left(15);
+        k.wrapping_mul(C2)
+    };
+
+    let mut h = seed;
+    let mut blocks = key.chunks_exact(4);
+    for b in &mut blocks {
+        h ^= mix(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        h = h.rotate_left(13);
+        h = h.wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, &b) in tail.iter().enumerate() {
+            k |= u32::from(b) << (8 * i);
+        }
+        h ^= mix(k);
+    }
+
+    h ^= key.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// Parses `name:value` features by hashing each name into one of a fixed
+/// number of buckets, producing a `Sparse` vector of that width.
+///
+/// The name is everything before the last `:`, so `user_country=US:1` is
+/// the feature `user_country=US`; a token without a `:` has value 1.
+/// Names are hashed with `murmur3_32` (seed 0 unless set), read as an
+/// `i32` `h`, and land in bucket `|h| % buckets`.  With signed hashing the
+/// value is negated when `h < 0`, so collisions tend to cancel rather than
+/// pile up.  This matches scikit-learn's `FeatureHasher`.  Features that
+/// share a bucket are summed.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct HashedSparseData {
+    buckets: usize,
+    seed: u32,
+    signed: bool
+}
+
+impl HashedSparseData {
+    /// Panics if `buckets` is 0
+    pub fn new(buckets: usize) -> Self {
+        assert!(buckets > 0, "need at least one bucket");
+        HashedSparseData { buckets, seed: 0, signed: false }
+    }
+
+    /// Sets the hash seed.  Defaults to 0.
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Flips the sign of values whose hash is negative.  Off by default.
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.signed = signed;
+        self
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.buckets
+    }
+
+    /// The bucket of `name`, and the sign applied to its value
+    pub fn bucket(&self, name: &str) -> (usize, f32) {
+        let h = murmur3_32(name.as_bytes(), self.seed) as i32;
+        let idx = h.unsigned_abs() as usize % self.buckets;
+        let sign = if self.signed && h < 0 { -1.0 } else { 1.0 };
+        (idx, sign)
+    }
+}
+
+impl DataParse for HashedSparseData {
+    type Out = Sparse;
+
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut out = Sparse::default();
+        self.parse_into(xs, &mut out)?;
+        Ok(out)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        let mut iv = Vec::new();
+        for x in xs {
+            let (name, v) = named_feature(x)?;
+            let (idx, sign) = self.bucket(name);
+            iv.push((idx, sign * v));
+        }
+        fill_summed(iv, self.buckets, out);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorKind;
+
+    #[test]
+    fn murmur_reference_values() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e_28b7);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248b_fa47);
+        assert_eq!(murmur3_32(b"The quick brown fox jumps over the lazy dog", 0), 0x2e4f_f723);
+    }
+
+    #[test]
+    fn hashes_named_features() {
+        let h = HashedSparseData::new(1 << 20);
+        let x = h.parse("user_country=US:1 price:3.2 clicked".split(' ')).unwrap();
+        assert_eq!((x.0, x.1.len()), (1 << 20, 3));
+        let (i, _) = h.bucket("price");
+        assert_eq!(x.2[x.1.iter().position(|&j| j == i).unwrap()], 3.2);
+
+        let one = HashedSparseData::new(1);
+        let x = one.parse("a:1 b:2".split(' ')).unwrap();
+        assert_eq!((x.1, x.2), (vec![0], vec![3.0]));
+        assert!(matches!(h.parse("a:b".split(' ')).err().unwrap().kind, ErrorKind::BadValue));
+    }
+
+    #[test]
+    fn signed_hashing() {
+        let plain = HashedSparseData::new(16);
+        let signed = HashedSparseData::new(16).signed(true);
+        let names = (0..64).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+        let negative = names.iter().filter(|n| signed.bucket(n).1 < 0.0).count();
+        assert!(negative > 0 && negative < 64);
+        for n in &names {
+            assert_eq!(plain.bucket(n).0, signed.bucket(n).0);
+            assert_eq!(plain.bucket(n).1, 1.0);
+        }
+    }
+}
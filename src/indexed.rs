@@ -0,0 +1,379 @@
+//! Random access to the rows of a file through a line-offset index
+//!
+//! `LineIndex` records where each row starts so that `IndexedReader` can
+//! parse any row without reading the ones before it.  The index can be
+//! saved next to the data as `<file>.idx` and is rebuilt when the data file
+//! changes.  Rows are read by seeking in the file; with the `mmap` feature,
+//! `IndexedReader::open_mapped` maps it instead.  Compressed files cannot
+//! be indexed.
+use std::borrow::Cow;
+use std::fs::{self,File};
+use std::io::{self,BufRead,BufReader,BufWriter,Seek,SeekFrom,Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use cache::{Cursor,Fingerprint};
+use compression::Compression;
+use error::ParseError;
+use options::ParseOptions;
+use qid::{NumericQid,QidReader};
+use types::DataParse;
+use super::{is_blank,parse_line_with,RowOf,TargetReader};
+
+const MAGIC: &[u8; 8] = b"SVMLINDX";
+const VERSION: u32 = 1;
+
+fn invalid<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Byte offsets and 1-based line numbers of the rows of a file
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct LineIndex {
+    fingerprint: Fingerprint,
+    offsets: Vec<u64>,
+    lines: Vec<usize>
+}
+
+impl LineIndex {
+    /// Indexes the rows read from `br`, skipping blank and comment-only
+    /// lines as `Reader` does
+    pub fn build<R: BufRead>(mut br: R, fingerprint: Fingerprint) -> io::Result<Self> {
+        let mut index = LineIndex { fingerprint, ..LineIndex::default() };
+        let (mut start, mut n) = (0, 0);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let size = br.read_until(b'\n', &mut buf)?;
+            if size == 0 { break }
+            n += 1;
+            let blank = ::std::str::from_utf8(&buf).is_ok_and(is_blank);
+            if !blank {
+                index.offsets.push(start);
+                index.lines.push(n);
+            }
+            start += size as u64;
+        }
+        Ok(index)
+    }
+
+    /// The data file the index was built from
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Byte offset of the start of row `i`
+    pub fn offset(&self, i: usize) -> Option<u64> {
+        self.offsets.get(i).cloned()
+    }
+
+    /// Line number of row `i`
+    pub fn line(&self, i: usize) -> Option<usize> {
+        self.lines.get(i).cloned()
+    }
+
+    /// Writes the index in a little-endian binary format
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(&self.fingerprint.encode())?;
+        w.write_all(&(self.len() as u64).to_le_bytes())?;
+        for (&o, &l) in self.offsets.iter().zip(&self.lines) {
+            w.write_all(&o.to_le_bytes())?;
+            w.write_all(&(l as u64).to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Reads an index written by `write_to`
+    pub fn from_bytes(b: &[u8]) -> io::Result<Self> {
+        if !b.starts_with(MAGIC) {
+            return Err(invalid("not a line index"))
+        }
+        let mut c = Cursor { b, pos: MAGIC.len() };
+        let version = c.u32()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported index version {}", version)))
+        }
+        c.u32()?;
+        let fingerprint = Fingerprint::decode(&mut c)?;
+        let rows = c.u64()? as usize;
+        if rows.checked_mul(16) != Some(b.len() - c.pos) {
+            return Err(invalid("corrupt line index"))
+        }
+        let mut index = LineIndex { fingerprint, offsets: Vec::with_capacity(rows), lines: Vec::with_capacity(rows) };
+        for _ in 0..rows {
+            index.offsets.push(c.u64()?);
+            index.lines.push(c.u64()? as usize);
+        }
+        Ok(index)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        LineIndex::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Opens `fname` for indexing, rejecting compressed files
+fn open_file(fname: &str) -> io::Result<BufReader<File>> {
+    let mut f = BufReader::new(File::open(fname)?);
+    if Compression::detect(f.fill_buf()?) != Compression::None {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{}: compressed files cannot be indexed", fname)))
+    }
+    Ok(f)
+}
+
+// Loads or builds the index of `f`, keeping the sidecar up to date if asked
+fn load_index(fname: &str, f: &mut BufReader<File>, sidecar: bool) -> io::Result<LineIndex> {
+    let path = format!("{}.idx", fname);
+    let fingerprint = Fingerprint::of(fname)?;
+    if sidecar {
+        if let Ok(index) = LineIndex::load(&path) {
+            if index.fingerprint() == fingerprint {
+                return Ok(index)
+            }
+        }
+    }
+    let index = LineIndex::build(&mut *f, fingerprint)?;
+    f.seek(SeekFrom::Start(0))?;
+    if sidecar {
+        let tmp = format!("{}.tmp", path);
+        index.save(&tmp)?;
+        fs::rename(&tmp, &path)?;
+    }
+    Ok(index)
+}
+
+// Where rows are read from
+enum Data {
+    File(Mutex<BufReader<File>>),
+    #[cfg(feature = "mmap")]
+    Map(Mmap)
+}
+
+impl Data {
+    // The line starting at `start`, without its line break
+    fn line(&self, start: u64) -> io::Result<Cow<'_, [u8]>> {
+        let mut line: Cow<'_, [u8]> = match *self {
+            Data::File(ref f) => {
+                let mut f = f.lock().unwrap();
+                f.seek(SeekFrom::Start(start))?;
+                let mut buf = Vec::new();
+                f.read_until(b'\n', &mut buf)?;
+                Cow::Owned(buf)
+            },
+            #[cfg(feature = "mmap")]
+            Data::Map(ref m) => {
+                let rest = m.get(start as usize..).unwrap_or(&[]);
+                let end = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |e| e + 1);
+                Cow::Borrowed(&rest[..end])
+            }
+        };
+        while let Some(&(b'\n' | b'\r')) = line.last() {
+            match line {
+                Cow::Owned(ref mut v) => { v.pop(); },
+                Cow::Borrowed(ref mut s) => *s = &s[..s.len() - 1]
+            }
+        }
+        Ok(line)
+    }
+}
+
+/// Parses rows of a file by position.
+///
+/// Each row is parsed on demand with `parse_line_with`, so errors carry the
+/// row's line and byte offset but no `ErrorPolicy` applies.
+pub struct IndexedReader<TR: TargetReader, P: DataParse, Q: QidReader = NumericQid> {
+    data: Data,
+    index: LineIndex,
+    tr: TR,
+    p: P,
+    opts: ParseOptions<Q>
+}
+
+impl <TR: TargetReader, P: DataParse> IndexedReader<TR, P> {
+    /// Indexes `fname` without saving the index
+    pub fn new(fname: &str, tr: TR, p: P) -> io::Result<Self> {
+        let mut f = open_file(fname)?;
+        let index = load_index(fname, &mut f, false)?;
+        Ok(IndexedReader { data: Data::File(Mutex::new(f)), index, tr, p, opts: ParseOptions::default() })
+    }
+
+    /// Indexes `fname` through its sidecar, `<fname>.idx`, which is reused
+    /// while it matches the file and rebuilt otherwise
+    pub fn open(fname: &str, tr: TR, p: P) -> io::Result<Self> {
+        let mut f = open_file(fname)?;
+        let index = load_index(fname, &mut f, true)?;
+        Ok(IndexedReader { data: Data::File(Mutex::new(f)), index, tr, p, opts: ParseOptions::default() })
+    }
+
+    /// Like `open`, but reads rows from a memory map of `fname`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written, truncated or replaced in place while
+    /// the reader exists; doing so is undefined behaviour.  Replacing it by
+    /// renaming another file over it is fine.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mapped(fname: &str, tr: TR, p: P) -> io::Result<Self> {
+        let mut f = open_file(fname)?;
+        let index = load_index(fname, &mut f, true)?;
+        let map = Mmap::map(f.get_ref())?;
+        Ok(IndexedReader { data: Data::Map(map), index, tr, p, opts: ParseOptions::default() })
+    }
+}
+
+impl <TR: TargetReader, P: DataParse, Q: QidReader> IndexedReader<TR, P, Q> {
+    /// Sets how lines are split into target, weight, qid and features
+    pub fn options<Q2: QidReader>(self, opts: ParseOptions<Q2>) -> IndexedReader<TR, P, Q2> {
+        IndexedReader { data: self.data, index: self.index, tr: self.tr, p: self.p, opts }
+    }
+
+    pub fn index(&self) -> &LineIndex {
+        &self.index
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Parses row `i`, or returns `None` past the end
+    pub fn get(&self, i: usize) -> Option<Result<RowOf<TR, P, Q>,ParseError>> {
+        let start = self.index.offset(i)?;
+        let line = self.index.line(i).unwrap();
+        Some(self.parse(start).map_err(|e| e.at(line, start)))
+    }
+
+    fn parse(&self, start: u64) -> Result<RowOf<TR, P, Q>,ParseError> {
+        let bytes = self.data.line(start)?;
+        let line = ::std::str::from_utf8(&bytes)
+            .map_err(|e| ParseError::from(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        parse_line_with(&self.tr, &self.p, &self.opts, line)
+    }
+
+    /// Iterates over the rows in `range`, clamped to the rows present
+    pub fn range(&self, range: Range<usize>) -> Rows<'_, TR, P, Q> {
+        let end = range.end.min(self.len());
+        Rows { reader: self, next: range.start.min(end), end }
+    }
+
+    /// Iterates over all rows
+    pub fn iter(&self) -> Rows<'_, TR, P, Q> {
+        self.range(0..self.len())
+    }
+}
+
+/// Rows of an `IndexedReader` in order
+pub struct Rows<'a, TR: TargetReader, P: DataParse, Q: QidReader> {
+    reader: &'a IndexedReader<TR, P, Q>,
+    next: usize,
+    end: usize
+}
+
+impl <'a, TR: TargetReader, P: DataParse, Q: QidReader> Iterator for Rows<'a, TR, P, Q> {
+    type Item = Result<RowOf<TR, P, Q>,ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None
+        }
+        self.next += 1;
+        self.reader.get(self.next - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.next;
+        (n, Some(n))
+    }
+}
+
+impl <'a, TR: TargetReader, P: DataParse, Q: QidReader> DoubleEndedIterator for Rows<'a, TR, P, Q> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.next == self.end {
+            return None
+        }
+        self.end -= 1;
+        self.reader.get(self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::TargetWeight;
+    use types::SparseData;
+    use super::super::{BinaryClassification,Regression};
+
+    #[test]
+    fn random_access() {
+        let path = ::std::env::temp_dir().join("svmloader_indexed.svm");
+        let fname = path.to_str().unwrap();
+        let idx = format!("{}.idx", fname);
+        let _ = fs::remove_file(&idx);
+        fs::write(&path, "# header\n1 0:1\r\n\n-1 qid:2 1:2\n1 1:z\n-1 0:3").unwrap();
+
+        let reader = IndexedReader::open(fname, BinaryClassification, SparseData::new(2)).unwrap();
+        assert_eq!(reader.len(), 4);
+        let row = reader.get(1).unwrap().unwrap();
+        assert_eq!((row.y, row.qid, row.x.1), (false, Some(2), vec![1]));
+        assert_eq!(reader.get(3).unwrap().unwrap().x.2, vec![3.0]);
+        let e = reader.get(2).unwrap().err().unwrap();
+        assert_eq!((e.line, e.offset), (5, 30));
+        assert!(reader.get(4).is_none());
+        let ys: Vec<bool> = reader.range(0..2).rev().map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![false, true]);
+        assert_eq!(reader.range(3..10).count(), 1);
+
+        let saved = LineIndex::load(&idx).unwrap();
+        assert_eq!(&saved, reader.index());
+        assert_eq!(saved.fingerprint(), Fingerprint::of(fname).unwrap());
+
+        drop(reader);
+        fs::write(&path, "1:2 0:1\n").unwrap();
+        let reader = IndexedReader::open(fname, Regression, SparseData::new(2)).unwrap()
+            .options(ParseOptions::new().weights(TargetWeight::Suffix));
+        assert_eq!(reader.len(), 1);
+        assert_eq!(reader.get(0).unwrap().unwrap().weight, Some(2.0));
+        assert_eq!(LineIndex::load(&idx).unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_matches_file() {
+        let path = ::std::env::temp_dir().join("svmloader_indexed_mapped.svm");
+        let fname = path.to_str().unwrap();
+        fs::write(&path, "1 0:1\r\n# c\n-1 1:2\n1 0:3").unwrap();
+        let seek = IndexedReader::new(fname, BinaryClassification, SparseData::new(2)).unwrap();
+        // The file is not modified while mapped
+        let map = unsafe { IndexedReader::open_mapped(fname, BinaryClassification, SparseData::new(2)).unwrap() };
+        let rows = |r: &IndexedReader<BinaryClassification, SparseData>| -> Vec<_> {
+            r.iter().map(|r| { let r = r.unwrap(); (r.y, r.x.1, r.x.2) }).collect()
+        };
+        assert_eq!(rows(&seek), rows(&map));
+        assert_eq!(map.len(), 3);
+    }
+}
@@ -1,11 +1,49 @@
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "bzip2")]
+extern crate bzip2;
+#[cfg(feature = "xz")]
+extern crate xz2;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+
+pub mod arff;
+pub mod cache;
+pub mod checkpoint;
+pub mod compression;
+pub mod dataset;
+pub mod delimited;
+pub mod error;
+pub mod groups;
+pub mod hashing;
+pub mod indexed;
+pub mod namespaces;
+pub mod options;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod qid;
+pub mod scan;
 pub mod types;
+pub mod vocab;
+pub mod vw;
+pub mod writer;
 
 use std::fmt::Debug;
 use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufReader,BufRead,Error};
+use std::io::{BufRead,Error};
+use std::str::SplitWhitespace;
 
-use types::DataParse;
+use checkpoint::Checkpoint;
+use compression::Source;
+use error::{ErrorKind,ErrorPolicy,ParseError};
+use groups::QueryGroups;
+use options::{Format,ParseOptions,TargetWeight};
+use qid::{NumericQid,QidReader};
+use types::{DataParse,parse_f32};
 
 pub trait TargetReader {
     type Out: Debug;
@@ -13,13 +51,21 @@ pub trait TargetReader {
     fn process(&self, data: &str) -> Option<Self::Out>;
 }
 
+impl <T: TargetReader + ?Sized> TargetReader for &T {
+    type Out = T::Out;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        (**self).process(data)
+    }
+}
+
 pub struct Regression;
 
 impl TargetReader for Regression {
     type Out = f32;
 
     fn process(&self, data: &str) -> Option<Self::Out> {
-        data.parse().ok()
+        parse_f32(data)
     }
 }
 
@@ -71,66 +117,250 @@ impl TargetReader for Tags {
     fn process(&self, data: &str) -> Option<Self::Out> {
         let mut classes = HashSet::new();
         for piece in data.split(',') {
-            if let Ok(tag) = piece.parse() {
-                classes.insert(tag);
-            }
+            classes.insert(piece.to_owned());
         }
         classes.remove("");
         Some(classes)
     }
 }
 
+/// The `key:value` meta tokens of a row, such as `sid:12`, in line order
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct Meta(Vec<(String,String)>);
+
+impl Meta {
+    pub fn new() -> Self {
+        Meta::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|e| e.0 == key).map(|e| e.1.as_str())
+    }
+
+    /// Sets `key`, replacing any earlier value
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        let (key, value) = (key.into(), value.into());
+        match self.0.iter_mut().find(|e| e.0 == key) {
+            Some(e) => e.1 = value,
+            None => self.0.push((key, value))
+        }
+    }
 
-pub struct Row<T,F> {
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.0.iter().map(|e| (e.0.as_str(), e.1.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+/// One parsed line.  `Q` is the query id type, set by the `QidReader` in
+/// `ParseOptions`.
+#[derive(Default)]
+pub struct Row<T,F,Q=usize> {
     pub y: T,
     pub x: F,
-    pub qid: Option<usize>,
+    pub qid: Option<Q>,
     pub comment: Option<String>,
+    pub weight: Option<f32>,
+    pub meta: Meta,
 }
 
 impl <T,F> Row<T,F> {
     pub fn new(y: T, x: F, qid: Option<usize>, comment: Option<String>) -> Self {
-        Row {
-            y: y,
-            x: x,
-            qid: qid,
-            comment: comment
-        }
+        Row { y, x, qid, comment, weight: None, meta: Meta::new() }
+    }
+}
+
+impl <T,F,Q> Row<T,F,Q> {
+    /// Replaces the query id, changing its type if need be
+    pub fn with_qid<Q2>(self, qid: Option<Q2>) -> Row<T,F,Q2> {
+        Row { y: self.y, x: self.x, qid, comment: self.comment, weight: self.weight, meta: self.meta }
+    }
+
+    pub fn with_weight(mut self, weight: Option<f32>) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_meta(mut self, meta: Meta) -> Self {
+        self.meta = meta;
+        self
     }
 }
 
-pub fn load<'a, TR: TargetReader, P: DataParse>(fname: &str, tr: &'a TR, p: &'a P) -> Result<Reader<'a, TR,P>,Error> {
-    let f = File::open(fname)?;
-    let br = BufReader::new(f);
-    Ok(Reader {br: br, p: p, tr: tr, tl: String::new()})
+/// Opens a file, transparently decompressing it when the matching codec
+/// feature is enabled.
+pub fn load<TR: TargetReader, P: DataParse>(fname: &str, tr: TR, p: P) -> Result<Reader<TR,P>,Error> {
+    Ok(from_reader(compression::open(fname)?, tr, p))
 }
 
-pub struct Reader<'a, TR: 'a + TargetReader,P: 'a + DataParse> {
-    br: BufReader<File>,
-    p: &'a P,
-    tr: &'a TR,
-    tl: String
+/// Reads rows from any buffered source, such as stdin or a decompressor
+pub fn from_reader<TR: TargetReader, P: DataParse, R: BufRead>(br: R, tr: TR, p: P) -> Reader<TR, P, R> {
+    Reader {
+        br, p, tr,
+        tl: String::new(),
+        line: 0,
+        offset: 0,
+        done: false,
+        policy: ErrorPolicy::default(),
+        opts: ParseOptions::default(),
+        blank: is_blank,
+        row_start: 0,
+        rows: 0,
+        skipped: 0
+    }
 }
 
-impl <'a, TR: 'a + TargetReader, P: 'a + DataParse> Iterator for Reader<'a, TR, P> {
-    type Item = Row<TR::Out, P::Out>;
+/// Reads rows from an in-memory string
+pub fn from_str<TR: TargetReader, P: DataParse>(data: &str, tr: TR, p: P) -> Reader<TR, P, &[u8]> {
+    from_reader(data.as_bytes(), tr, p)
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            self.tl.clear();
-            if let Ok(size) = self.br.read_line(&mut self.tl) {
-                if size == 0 { return None }
-                let res = parse_line(self.tr, self.p, &self.tl);
+/// Streams rows from a `BufRead` source.  Malformed lines are handled according
+/// to the reader's `ErrorPolicy`; an I/O error is always yielded and ends the stream.
+///
+/// The reader owns its `TargetReader` and `DataParse`; pass references to
+/// share them between readers instead.
+pub struct Reader<TR: TargetReader,P: DataParse, R: BufRead = Source, Q: QidReader = NumericQid> {
+    br: R,
+    p: P,
+    tr: TR,
+    tl: String,
+    line: usize,
+    offset: u64,
+    done: bool,
+    policy: ErrorPolicy,
+    opts: ParseOptions<Q>,
+    blank: fn(&str) -> bool,
+    row_start: u64,
+    rows: usize,
+    skipped: usize
+}
 
-                if res.is_some() { return res }
+impl <TR: TargetReader, P: DataParse, R: BufRead, Q: QidReader> Reader<TR, P, R, Q> {
+    /// Sets how malformed lines are handled.  Defaults to `ErrorPolicy::Strict`.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
 
-            } else { 
-                return None 
+    /// Sets how lines are split into target, weight, qid and features
+    pub fn options<Q2: QidReader>(self, opts: ParseOptions<Q2>) -> Reader<TR, P, R, Q2> {
+        Reader {
+            br: self.br, p: self.p, tr: self.tr,
+            tl: self.tl,
+            line: self.line,
+            offset: self.offset,
+            done: self.done,
+            policy: self.policy,
+            opts,
+            blank: self.blank,
+            row_start: self.row_start,
+            rows: self.rows,
+            skipped: self.skipped
+        }
+    }
+
+    /// Groups consecutive rows by qid; see `QueryGroups`
+    pub fn query_groups(self) -> QueryGroups<Self, TR::Out, P::Out, Q::Out>
+        where Q::Out: Clone + std::hash::Hash + Eq
+    {
+        QueryGroups::new(self).with_position(Reader::position)
+    }
+
+    /// Number of malformed lines dropped so far
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    // Line number and byte offset of the last data line read
+    fn position(&self) -> (usize, u64) {
+        (self.line, self.row_start)
+    }
+
+    /// Position just past the last line read, for resuming with
+    /// `checkpoint::resume`
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { offset: self.offset, row: self.rows, line: self.line, skipped: self.skipped }
+    }
+
+    /// Reads the next row into `row`, reusing its buffers, so a training
+    /// loop can stream a file without allocating per row.  Returns `None` at
+    /// the end of input.
+    pub fn read_into(&mut self, row: &mut Row<TR::Out, P::Out, Q::Out>) -> Option<Result<(),ParseError>> {
+        self.read_next(|tr, p, opts, line| parse_line_into_with(tr, p, opts, line, row))
+    }
+
+    // Feeds each data line to `parse`, applying the error policy
+    fn read_next<T, F>(&mut self, mut parse: F) -> Option<Result<T,ParseError>>
+        where F: FnMut(&TR, &P, &ParseOptions<Q>, &str) -> Result<T,ParseError>
+    {
+        while !self.done {
+            self.tl.clear();
+            let start = self.offset;
+            match self.br.read_line(&mut self.tl) {
+                Ok(0) => self.done = true,
+                Ok(size) => {
+                    self.line += 1;
+                    self.offset += size as u64;
+                    let line = self.tl.trim_end_matches(['\n', '\r']);
+                    if (self.blank)(line) { continue }
+                    self.row_start = start;
+
+                    match parse(&self.tr, &self.p, &self.opts, line) {
+                        Ok(row) => {
+                            self.rows += 1;
+                            return Some(Ok(row))
+                        },
+                        Err(e) => {
+                            let e = e.at(self.line, start);
+                            match self.policy {
+                                ErrorPolicy::Strict => {
+                                    self.done = true;
+                                    return Some(Err(e))
+                                },
+                                ErrorPolicy::Skip => self.skipped += 1,
+                                ErrorPolicy::Callback(ref mut f) => {
+                                    f(line, &e);
+                                    self.skipped += 1;
+                                }
+                            }
+                        }
+                    }
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(ParseError::from(e).at(self.line + 1, start)))
+                }
             }
         }
+        None
+    }
+}
+
+impl <TR: TargetReader, P: DataParse, R: BufRead, Q: QidReader> Iterator for Reader<TR, P, R, Q> {
+    type Item = Result<Row<TR::Out, P::Out, Q::Out>,ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_next(parse_line_with)
     }
 }
 
+// Empty and comment-only lines carry no row
+fn is_blank(line: &str) -> bool {
+    line.split('#').next().unwrap().trim().is_empty()
+}
+
 struct IterCons<X,I>(Option<X>, I);
 
 impl <X, I: Iterator<Item=X>> Iterator for IterCons<X, I> {
@@ -145,41 +375,121 @@ impl <X, I: Iterator<Item=X>> Iterator for IterCons<X, I> {
     }
 }
 
-pub fn parse_line<TR: TargetReader, DP: DataParse>(tr: &TR, dp: &DP, line: &str) -> Option<Row<TR::Out,DP::Out>> {
+// A line split into its parts, with the features left as unparsed tokens
+struct Fields<'a, T, Q> {
+    y: T,
+    qid: Option<Q>,
+    weight: Option<f32>,
+    meta: Vec<(&'a str, &'a str)>,
+    comment: Option<&'a str>,
+    features: IterCons<&'a str, SplitWhitespace<'a>>
+}
+
+fn parse_weight(token: &str, w: &str) -> Result<f32,ParseError> {
+    parse_f32(w).ok_or_else(|| ParseError::new(ErrorKind::BadWeight, token))
+}
+
+// Splits a line in the format set by `opts`.  Formats whose features need
+// rewriting leave them in `buf`.
+fn fields<'a, TR: TargetReader, Q: QidReader>(tr: &TR, opts: &ParseOptions<Q>, line: &'a str, buf: &'a mut String) -> Result<Fields<'a, TR::Out, Q::Out>,ParseError> {
+    match opts.format {
+        Format::SvmLight => svmlight_fields(tr, opts, line),
+        Format::Vw => vw::fields(tr, opts, line, buf)
+    }
+}
+
+fn svmlight_fields<'a, TR: TargetReader, Q: QidReader>(tr: &TR, opts: &ParseOptions<Q>, line: &'a str) -> Result<Fields<'a, TR::Out, Q::Out>,ParseError> {
     let has_target = !line.starts_with(' ');
     // Remove comments
-    let mut data = line.split('#');
+    let mut data = line.splitn(2, '#');
     let line = data.next().unwrap();
-    let comment = data.next().map(|x| x.to_owned());
-    let mut pieces = line.trim().split_whitespace();
-    let target = if has_target {
-        pieces.next().and_then(|x| tr.process(x))
-    } else {
-        tr.process("")
+    let comment = data.next();
+    let mut pieces = line.split_whitespace();
+    let mut weight = None;
+    let t = if has_target { pieces.next().unwrap_or("") } else { "" };
+    let label = match opts.weights {
+        TargetWeight::Suffix if t.contains(':') => {
+            let (label, w) = t.split_at(t.rfind(':').unwrap());
+            weight = Some(parse_weight(t, &w[1..])?);
+            label
+        },
+        _ => t
     };
-
-    // Check for qid
-    let maybe_qid = pieces.next();
-    let qid: Option<usize> = maybe_qid.and_then(|qid| {
-        if qid.starts_with("qid:") {
-            let mut p = qid.split(':').skip(1);
-            p.next().unwrap().parse().ok()
+    let y = tr.process(label).ok_or_else(|| ParseError::new(ErrorKind::BadTarget, t))?;
+
+    // Meta tokens sit between the target and the features
+    let mut qid = None;
+    let mut meta = Vec::new();
+    let mut next = pieces.next();
+    // Only a number is a column weight, so `|ns` and bare names are left
+    if opts.weights == TargetWeight::Column {
+        if let Some(w) = next.filter(|w| !w.contains(':')).and_then(parse_f32) {
+            weight = Some(w);
+            next = pieces.next();
+        }
+    }
+    while let Some(token) = next {
+        if let Some(id) = token.strip_prefix("qid:") {
+            qid = Some(opts.qids.process(id).ok_or_else(|| ParseError::new(ErrorKind::BadQid, token))?);
+        } else if let Some(w) = token.strip_prefix("cost:") {
+            weight = Some(parse_weight(token, w)?);
         } else {
-            None
+            match token.split_once(':') {
+                Some((k, v)) if opts.is_meta_key(k) => meta.push((k, v)),
+                _ => break
+            }
         }
-    });
-    let peeked = if qid.is_some() {
-        IterCons(None, pieces)
-    } else {
-        IterCons(maybe_qid, pieces)
-    };
+        next = pieces.next();
+    }
 
-    let vec = dp.parse(peeked);
+    Ok(Fields { y, qid, weight, meta, comment, features: IterCons(next, pieces) })
+}
 
-    match (target, vec) {
-        (Some(y), Some(x)) => Some(Row::new(y, x, qid, comment)),
-        _ => None
+pub fn parse_line<TR: TargetReader, DP: DataParse>(tr: &TR, dp: &DP, line: &str) -> Result<Row<TR::Out,DP::Out>,ParseError> {
+    parse_line_with(tr, dp, &ParseOptions::default(), line)
+}
+
+// The row produced by a given target reader, parser and qid reader
+type RowOf<TR, DP, Q> = Row<<TR as TargetReader>::Out, <DP as DataParse>::Out, <Q as QidReader>::Out>;
+
+/// Parses a line according to `opts`
+pub fn parse_line_with<TR: TargetReader, DP: DataParse, Q: QidReader>(tr: &TR, dp: &DP, opts: &ParseOptions<Q>, line: &str) -> Result<RowOf<TR, DP, Q>,ParseError> {
+    let mut buf = String::new();
+    let f = fields(tr, opts, line, &mut buf)?;
+    let x = dp.parse(f.features)?;
+    let mut meta = Meta::new();
+    for (k, v) in f.meta {
+        meta.insert(k, v);
     }
+    Ok(Row { y: f.y, x, qid: f.qid, comment: f.comment.map(|c| c.to_owned()), weight: f.weight, meta })
+}
+
+/// Like `parse_line`, but overwrites `row` in place so that its feature
+/// buffers and comment string are reused rather than reallocated.
+pub fn parse_line_into<TR: TargetReader, DP: DataParse>(tr: &TR, dp: &DP, line: &str, row: &mut Row<TR::Out,DP::Out>) -> Result<(),ParseError> {
+    parse_line_into_with(tr, dp, &ParseOptions::default(), line, row)
+}
+
+/// `parse_line_into` according to `opts`
+pub fn parse_line_into_with<TR: TargetReader, DP: DataParse, Q: QidReader>(tr: &TR, dp: &DP, opts: &ParseOptions<Q>, line: &str, row: &mut RowOf<TR, DP, Q>) -> Result<(),ParseError> {
+    let mut buf = String::new();
+    let f = fields(tr, opts, line, &mut buf)?;
+    dp.parse_into(f.features, &mut row.x)?;
+    row.y = f.y;
+    row.qid = f.qid;
+    row.weight = f.weight;
+    row.meta.clear();
+    for (k, v) in f.meta {
+        row.meta.insert(k, v);
+    }
+    match (f.comment, row.comment.as_mut()) {
+        (Some(c), Some(buf)) => {
+            buf.clear();
+            buf.push_str(c);
+        },
+        (c, _) => row.comment = c.map(|c| c.to_owned())
+    }
+    Ok(())
 }
 
 
@@ -189,12 +499,12 @@ mod tests {
     use types::*;
     #[test]
     fn parse_line_1() {
-        let sd = SparseData(12);
+        let sd = SparseData::new(12);
         let td = DisjointClassification;
 
         let s = "1 qid:1234 0:-13 11:10 # hello";
         let srow = parse_line(&td, &sd, s);
-        assert!(srow.is_some());
+        assert!(srow.is_ok());
         let row = srow.unwrap();
 
         assert_eq!(row.y, 1usize);
@@ -202,18 +512,141 @@ mod tests {
         assert_eq!(row.comment, Some(" hello".into()));
     }
 
+    #[test]
     fn parse_bool_1() {
-        let sd = SparseData(12);
+        let sd = SparseData::new(12);
         let td = BinaryClassification;
 
         let s2 = "-1 qid:1234 0:-13 11:10 # hello";
         let srow = parse_line(&td, &sd, s2);
-        assert!(srow.is_some());
+        assert!(srow.is_ok());
         let row = srow.unwrap();
 
-        assert_eq!(row.y, false);
+        assert!(!row.y);
         assert_eq!(row.qid, Some(1234));
         assert_eq!(row.comment, Some(" hello".into()));
 
     }
+
+    #[test]
+    fn parse_errors() {
+        let sd = SparseData::new(12);
+        let td = BinaryClassification;
+
+        let kind = |s| parse_line(&td, &sd, s).err().unwrap();
+        let e = kind("2 0:1");
+        assert!(matches!(e.kind, ErrorKind::BadTarget));
+        assert_eq!(e.token, "2");
+        assert!(matches!(kind("1 qid:abc 0:1").kind, ErrorKind::BadQid));
+        assert!(matches!(kind("1 x:1").kind, ErrorKind::BadIndex));
+        let e = kind("1 0:1 3:y");
+        assert!(matches!(e.kind, ErrorKind::BadValue));
+        assert_eq!(e.token, "3:y");
+    }
+
+    #[test]
+    fn reader_positions() {
+        use std::io::Write;
+        let path = std::env::temp_dir().join("svmloader_reader_positions.svm");
+        {
+            let mut f = std::fs::File::create(&path).unwrap();
+            f.write_all(b"1 0:1\n\n# header\n1 0:z\n-1 1:2\n").unwrap();
+        }
+        let fname = path.to_str().unwrap();
+        let rows: Vec<_> = load(fname, &BinaryClassification, SparseData::new(2))
+            .unwrap().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].is_ok());
+        let e = rows[1].as_ref().err().unwrap();
+        assert_eq!((e.line, e.offset), (4, 16));
+
+        let mut reader = load(fname, &BinaryClassification, SparseData::new(2))
+            .unwrap().on_error(ErrorPolicy::Skip);
+        let ys: Vec<bool> = reader.by_ref().map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![true, false]);
+        assert_eq!(reader.skipped(), 1);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let reader = load(fname, &BinaryClassification, SparseData::new(2))
+            .unwrap().on_error(ErrorPolicy::Callback(Box::new(move |l, e| {
+                tx.send((l.to_owned(), e.line)).unwrap();
+            })));
+        assert_eq!(reader.count(), 2);
+        assert_eq!(rx.recv().unwrap(), ("1 0:z".to_owned(), 4));
+    }
+
+    #[test]
+    fn read_from_str() {
+        let data = "0.5 0:1 2:3\n 1:1\n-2 qid:7 1:4";
+        let res: Result<Vec<_>,_> = from_str(data, &Regression, DenseData::new()).collect();
+        assert_eq!(res.err().unwrap().line, 2);
+
+        let ys: Vec<f32> = from_reader(&b"1.5 0:1\n-2 qid:7 1:4\n"[..], &Regression, DenseData::new())
+            .map(|r| r.unwrap().y).collect();
+        assert_eq!(ys, vec![1.5, -2.0]);
+    }
+
+    fn open_owned(data: &'static str) -> Reader<Regression, SparseData, &'static [u8]> {
+        from_str(data, Regression, SparseData::new(4))
+    }
+
+    #[test]
+    fn owned_reader_is_send() {
+        let reader = open_owned("1 0:1\n2 3:1\n");
+        let ys = std::thread::spawn(move || {
+            reader.map(|r| r.unwrap().y).collect::<Vec<f32>>()
+        }).join().unwrap();
+        assert_eq!(ys, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn read_into_reuses_row() {
+        let mut reader = from_str("1 0:1 3:2 # a\n-1 1:1 # b\n1 2:5\n", BinaryClassification, SparseData::new(4));
+        let mut row = Row::default();
+        let mut seen = Vec::new();
+        while let Some(res) = reader.read_into(&mut row) {
+            res.unwrap();
+            seen.push((row.y, row.x.1.clone(), row.comment.clone()));
+        }
+        assert_eq!(seen, vec![
+            (true, vec![0, 3], Some(" a".to_owned())),
+            (false, vec![1], Some(" b".to_owned())),
+            (true, vec![2], None)
+        ]);
+    }
+
+    #[test]
+    fn parse_weights() {
+        let sd = SparseData::new(4);
+        let w = |opts: ParseOptions, s| {
+            parse_line_with(&Regression, &sd, &opts, s).map(|r| (r.y, r.weight, r.qid, r.x.1))
+        };
+        assert_eq!(w(ParseOptions::new(), "2 cost:0.5 qid:3 1:1").unwrap(), (2.0, Some(0.5), Some(3), vec![1]));
+        assert_eq!(w(ParseOptions::new(), "2 1:1").unwrap(), (2.0, None, None, vec![1]));
+
+        let suffix = || ParseOptions::new().weights(TargetWeight::Suffix);
+        assert_eq!(w(suffix(), "2:0.25 qid:1 1:1").unwrap(), (2.0, Some(0.25), Some(1), vec![1]));
+        assert_eq!(w(suffix(), "2 1:1").unwrap(), (2.0, None, None, vec![1]));
+        assert!(matches!(w(suffix(), "2:x 1:1").err().unwrap().kind, ErrorKind::BadWeight));
+
+        let column = || ParseOptions::new().weights(TargetWeight::Column);
+        assert_eq!(w(column(), "2 3 2:1").unwrap(), (2.0, Some(3.0), None, vec![2]));
+        assert_eq!(w(column(), "2 2:1").unwrap(), (2.0, None, None, vec![2]));
+    }
+
+    #[test]
+    fn parse_meta() {
+        let sd = SparseData::new(4);
+        let row = parse_line(&Regression, &sd, "1 sid:9 qid:2 cost:2 0:1 # c").unwrap();
+        assert_eq!((row.qid, row.weight, row.meta.get("sid")), (Some(2), Some(2.0), Some("9")));
+        assert!(matches!(parse_line(&Regression, &sd, "1 doc:a 0:1").err().unwrap().kind, ErrorKind::BadIndex));
+
+        let opts = ParseOptions::new().meta_key("doc");
+        let row = parse_line_with(&Regression, &sd, &opts, "1 doc:a-7 sid:3 doc:b 3:1").unwrap();
+        assert_eq!(row.meta.iter().collect::<Vec<_>>(), vec![("doc", "b"), ("sid", "3")]);
+        assert_eq!(row.x.1, vec![3]);
+
+        let opts = ParseOptions::new().meta_keys(Vec::<String>::new());
+        assert!(parse_line_with(&Regression, &sd, &opts, "1 sid:3 0:1").is_err());
+    }
 }
@@ -0,0 +1,225 @@
+//! Sparse rows split into named feature groups
+use error::{ErrorKind,ParseError};
+use types::{fill_summed,DataParse,Sparse};
+
+/// A sparse row whose features are grouped into namespaces.
+///
+/// Each namespace holds sorted indices in the same index space of width
+/// `dims`, so one index may appear in several namespaces.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct NamespacedSparse {
+    dims: usize,
+    names: Vec<String>,
+    starts: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f32>
+}
+
+impl NamespacedSparse {
+    pub fn new() -> Self {
+        NamespacedSparse::default()
+    }
+
+    fn clear(&mut self) {
+        self.dims = 0;
+        self.names.clear();
+        self.starts.clear();
+        self.indices.clear();
+        self.values.clear();
+    }
+
+    /// Appends a namespace holding the features of `x`
+    pub fn push(&mut self, name: &str, x: &Sparse) {
+        self.dims = self.dims.max(x.0);
+        self.names.push(name.to_owned());
+        self.starts.push(self.indices.len());
+        self.indices.extend_from_slice(&x.1);
+        self.values.extend_from_slice(&x.2);
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// Namespace names in line order; features before any `|` are in ""
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Indices and values of the `k`th namespace
+    pub fn get(&self, k: usize) -> (&[usize], &[f32]) {
+        let end = self.starts.get(k + 1).cloned().unwrap_or(self.indices.len());
+        let r = self.starts[k]..end;
+        (&self.indices[r.clone()], &self.values[r])
+    }
+
+    /// Indices and values of the first namespace called `name`
+    pub fn namespace(&self, name: &str) -> Option<(&[usize], &[f32])> {
+        self.names.iter().position(|n| n == name).map(|k| self.get(k))
+    }
+
+    fn filter<F: Fn(&str) -> bool>(&self, keep: F) -> NamespacedSparse {
+        let mut out = NamespacedSparse { dims: self.dims, ..NamespacedSparse::default() };
+        for (k, name) in self.names.iter().enumerate() {
+            if keep(name) {
+                let (is, vs) = self.get(k);
+                out.names.push(name.clone());
+                out.starts.push(out.indices.len());
+                out.indices.extend_from_slice(is);
+                out.values.extend_from_slice(vs);
+            }
+        }
+        out
+    }
+
+    /// Keeps only the namespaces named in `names`
+    pub fn select(&self, names: &[&str]) -> NamespacedSparse {
+        self.filter(|n| names.contains(&n))
+    }
+
+    /// Removes the namespaces named in `names`
+    pub fn without(&self, names: &[&str]) -> NamespacedSparse {
+        self.filter(|n| !names.contains(&n))
+    }
+
+    /// Pairwise products of the features of namespaces `a` and `b`.  The
+    /// pair `(i, j)` gets index `i * dims + j` in a space of width `dims²`.
+    /// A missing namespace crosses to an empty vector.  Fails with
+    /// `ErrorKind::IndexOutOfRange` when `dims²` overflows a `usize`.
+    pub fn cross(&self, a: &str, b: &str) -> Result<Sparse,ParseError> {
+        let dims = self.dims;
+        let overflow = || ParseError::new(ErrorKind::IndexOutOfRange, format!("{}*{}", a, b));
+        let width = dims.checked_mul(dims).ok_or_else(overflow)?;
+        let mut iv = Vec::new();
+        if let (Some((ia, va)), Some((ib, vb))) = (self.namespace(a), self.namespace(b)) {
+            for (&i, &x) in ia.iter().zip(va) {
+                for (&j, &y) in ib.iter().zip(vb) {
+                    let k = i.checked_mul(dims).and_then(|k| k.checked_add(j))
+                        .filter(|&k| k < width).ok_or_else(overflow)?;
+                    iv.push((k, x * y));
+                }
+            }
+        }
+        let mut out = Sparse::default();
+        fill_summed(iv, width, &mut out);
+        Ok(out)
+    }
+
+    /// All namespaces merged into one row, summing shared indices
+    pub fn to_sparse(&self) -> Sparse {
+        let iv = self.indices.iter().cloned().zip(self.values.iter().cloned()).collect();
+        let mut out = Sparse::default();
+        fill_summed(iv, self.dims, &mut out);
+        out
+    }
+}
+
+/// Splits features at `|ns` tokens and parses each group with `P`.
+///
+/// In SVMlight lines the separators are written as tokens of their own,
+/// `1 |user 3:1 |ad 7:1`; for VW lines use `Namespaces::Separate`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NamespacedData<P> {
+    inner: P
+}
+
+impl <P: DataParse<Out=Sparse>> NamespacedData<P> {
+    pub fn new(inner: P) -> Self {
+        NamespacedData { inner }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+}
+
+impl <P: DataParse<Out=Sparse>> DataParse for NamespacedData<P> {
+    type Out = NamespacedSparse;
+
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut out = NamespacedSparse::default();
+        self.parse_into(xs, &mut out)?;
+        Ok(out)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        out.clear();
+        let mut group = Vec::new();
+        let mut name = None;
+        let mut x = Sparse::default();
+        for token in xs {
+            match token.strip_prefix('|') {
+                Some(next) => {
+                    if name.is_some() || !group.is_empty() {
+                        self.inner.parse_into(group.drain(..), &mut x)?;
+                        out.push(name.unwrap_or(""), &x);
+                    }
+                    name = Some(next);
+                },
+                None => group.push(token)
+            }
+        }
+        if name.is_some() || !group.is_empty() {
+            self.inner.parse_into(group.drain(..), &mut x)?;
+            out.push(name.unwrap_or(""), &x);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::{Format,Namespaces,ParseOptions,TargetWeight};
+    use types::SparseData;
+    use vocab::Vocabulary;
+    use super::super::{parse_line,parse_line_with,Regression};
+
+    #[test]
+    fn select_without_cross() {
+        let p = NamespacedData::new(SparseData::new(10));
+        let row = parse_line(&Regression, &p, "1 0:1 |user 3:2 5:1 |ad 3:4 |empty").unwrap();
+        let x = row.x;
+        assert_eq!(x.names(), &["", "user", "ad", "empty"]);
+        assert_eq!(x.namespace("user"), Some((&[3, 5][..], &[2.0, 1.0][..])));
+        assert_eq!(x.select(&["ad", "user"]).names(), &["user", "ad"]);
+        assert_eq!(x.without(&["user"]).to_sparse().1, vec![0, 3]);
+        assert_eq!(x.to_sparse().2, vec![1.0, 6.0, 1.0]);
+
+        let c = x.cross("user", "ad").unwrap();
+        assert_eq!((c.0, c.1, c.2), (100, vec![33, 53], vec![8.0, 4.0]));
+        assert!(x.cross("user", "none").unwrap().1.is_empty());
+
+        let mut wide = NamespacedSparse::new();
+        wide.push("a", &Sparse(usize::MAX, vec![1], vec![1.0]));
+        assert!(matches!(wide.cross("a", "a").err().unwrap().kind, ErrorKind::IndexOutOfRange));
+    }
+
+    #[test]
+    fn column_weights() {
+        let p = NamespacedData::new(SparseData::new(10));
+        let opts = ParseOptions::new().weights(TargetWeight::Column);
+        let row = parse_line_with(&Regression, &p, &opts, "1 |a 3:1").unwrap();
+        assert_eq!((row.weight, row.x.names()), (None, &["a".to_owned()][..]));
+        let row = parse_line_with(&Regression, &p, &opts, "1 0.5 |a 3:1 |b 4:2").unwrap();
+        assert_eq!((row.weight, row.x.len()), (Some(0.5), 2));
+    }
+
+    #[test]
+    fn vw_namespaces() {
+        let p = NamespacedData::new(Vocabulary::new());
+        let opts = ParseOptions::new().format(Format::Vw).namespaces(Namespaces::Separate);
+        let row = parse_line_with(&Regression, &p, &opts, "1 |a x y:2 |b:3 x").unwrap();
+        assert_eq!(row.x.names(), &["a", "b"]);
+        assert_eq!(row.x.get(1), (&[0][..], &[3.0][..]));
+        assert_eq!(p.inner().names(), vec!["x", "y"]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Settings for how a line is split into its parts
+use qid::{NumericQid,QidReader};
+
+/// Where a row's weight is read from besides the SVMlight `cost:` token,
+/// which is always recognised
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum TargetWeight {
+    /// Only `cost:` sets a weight
+    #[default]
+    None,
+    /// `label:weight` in the target token
+    Suffix,
+    /// `label weight`: a bare number right after the target
+    Column
+}
+
+/// The text format of each line
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Format {
+    /// `target [qid:..] [cost:..] idx:value ... # comment`
+    #[default]
+    SvmLight,
+    /// Vowpal Wabbit: `label [importance] ['tag] |ns feat:value ...`; see
+    /// the `vw` module
+    Vw
+}
+
+/// How Vowpal Wabbit namespaces are reflected in feature names
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Namespaces {
+    /// `feat` in namespace `ns` is passed on as `ns^feat`
+    #[default]
+    Prefix,
+    /// Namespaces are dropped and `feat` is passed on as is
+    Flatten,
+    /// Each namespace is announced by a `|ns` token, for `NamespacedData`
+    Separate
+}
+
+/// Options shared by `parse_line_with` and the readers.  `Q` reads the
+/// value of `qid:` tokens.
+#[derive(Debug,Clone)]
+pub struct ParseOptions<Q: QidReader = NumericQid> {
+    pub(crate) format: Format,
+    pub(crate) namespaces: Namespaces,
+    pub(crate) weights: TargetWeight,
+    pub(crate) meta_keys: Vec<String>,
+    pub(crate) qids: Q
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            format: Format::SvmLight,
+            namespaces: Namespaces::Prefix,
+            weights: TargetWeight::None,
+            meta_keys: vec!["sid".to_owned()],
+            qids: NumericQid
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        ParseOptions::default()
+    }
+}
+
+impl <Q: QidReader> ParseOptions<Q> {
+    /// Sets how `qid:` values are read.  Defaults to `NumericQid`.
+    pub fn qids<Q2: QidReader>(self, qids: Q2) -> ParseOptions<Q2> {
+        ParseOptions {
+            format: self.format,
+            namespaces: self.namespaces,
+            weights: self.weights,
+            meta_keys: self.meta_keys,
+            qids
+        }
+    }
+
+    /// Sets the line format.  Defaults to `Format::SvmLight`.
+    pub fn format(mut self, f: Format) -> Self {
+        self.format = f;
+        self
+    }
+
+    /// Sets how VW namespaces are named.  Defaults to `Namespaces::Prefix`.
+    pub fn namespaces(mut self, ns: Namespaces) -> Self {
+        self.namespaces = ns;
+        self
+    }
+
+    /// Sets which `key:value` tokens before the features are collected into
+    /// `Row::meta`.  Defaults to `sid`.  `qid` and `cost` are always
+    /// recognised and keep their own fields.
+    pub fn meta_keys<I, S>(mut self, keys: I) -> Self
+        where I: IntoIterator<Item=S>, S: Into<String>
+    {
+        self.meta_keys = keys.into_iter().map(|k| k.into()).collect();
+        self
+    }
+
+    /// Adds one key to the recognised meta keys
+    pub fn meta_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.meta_keys.push(key.into());
+        self
+    }
+
+    // The same options borrowing this qid reader
+    pub(crate) fn by_ref(&self) -> ParseOptions<&Q> {
+        ParseOptions {
+            format: self.format,
+            namespaces: self.namespaces,
+            weights: self.weights,
+            meta_keys: self.meta_keys.clone(),
+            qids: &self.qids
+        }
+    }
+
+    pub(crate) fn is_meta_key(&self, key: &str) -> bool {
+        self.meta_keys.iter().any(|k| k == key)
+    }
+
+    /// Sets where weights are read from.  Defaults to `TargetWeight::None`.
+    pub fn weights(mut self, w: TargetWeight) -> Self {
+        self.weights = w;
+        self
+    }
+}
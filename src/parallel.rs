@@ -0,0 +1,300 @@
+//! Multi-threaded parsing of large files
+//!
+//! The file is cut into byte ranges that end on a newline, and each range is
+//! read and parsed as a task on the rayon thread pool.  Only plain,
+//! uncompressed files can be split this way.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self,Read,Seek,SeekFrom};
+use std::path::{Path,PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel,Receiver,Sender};
+use std::vec;
+
+use rayon;
+
+use compression::Compression;
+use error::{ErrorKind,ParseError};
+use options::ParseOptions;
+use qid::{NumericQid,QidReader};
+use types::DataParse;
+use super::{is_blank,parse_line_with,Row,TargetReader};
+
+type Item<TR, P, Q> = Result<Row<<TR as TargetReader>::Out, <P as DataParse>::Out, <Q as QidReader>::Out>,ParseError>;
+type Batch<TR, P, Q> = Chunk<Item<TR, P, Q>>;
+
+struct Chunk<T> {
+    id: usize,
+    lines: usize,
+    rows: Vec<T>
+}
+
+/// Opens a plain file for parallel parsing
+pub fn load_parallel<TR, P>(fname: &str, tr: TR, p: P) -> io::Result<ParallelReader<TR, P>>
+    where TR: TargetReader + Send + Sync + 'static, TR::Out: Send,
+          P: DataParse + Send + Sync + 'static, P::Out: Send
+{
+    let mut f = File::open(fname)?;
+    let mut magic = [0u8; 6];
+    let n = f.read(&mut magic)?;
+    if Compression::detect(&magic[..n]) != Compression::None {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            "compressed input cannot be split for parallel parsing"))
+    }
+    let len = f.metadata()?.len();
+    let (tx, rx) = channel();
+    Ok(ParallelReader {
+        path: PathBuf::from(fname),
+        f, len,
+        tr: Arc::new(tr),
+        p: Arc::new(p),
+        opts: Arc::new(ParseOptions::default()),
+        chunk_size: 16 << 20,
+        max_in_flight: 2 * rayon::current_num_threads(),
+        ordered: true,
+        dispatched: 0,
+        split_at: 0,
+        in_flight: 0,
+        tx, rx,
+        pending: BTreeMap::new(),
+        next_id: 0,
+        lines_before: 0,
+        current: Vec::new().into_iter()
+    })
+}
+
+/// Yields rows parsed on the rayon thread pool.
+///
+/// In ordered mode (the default) rows come out in file order and errors carry
+/// exact line numbers.  In unordered mode rows are yielded as soon as their
+/// chunk is done; errors then only know their byte `offset` and report line 0.
+pub struct ParallelReader<TR: TargetReader, P: DataParse, Q: QidReader = NumericQid> {
+    path: PathBuf,
+    f: File,
+    len: u64,
+    tr: Arc<TR>,
+    p: Arc<P>,
+    opts: Arc<ParseOptions<Q>>,
+    chunk_size: u64,
+    max_in_flight: usize,
+    ordered: bool,
+    dispatched: usize,
+    split_at: u64,
+    in_flight: usize,
+    tx: Sender<Batch<TR, P, Q>>,
+    rx: Receiver<Batch<TR, P, Q>>,
+    pending: BTreeMap<usize, Batch<TR, P, Q>>,
+    next_id: usize,
+    lines_before: usize,
+    current: vec::IntoIter<Item<TR, P, Q>>
+}
+
+impl <TR, P, Q> ParallelReader<TR, P, Q>
+    where TR: TargetReader + Send + Sync + 'static, TR::Out: Send,
+          P: DataParse + Send + Sync + 'static, P::Out: Send,
+          Q: QidReader + Send + Sync + 'static, Q::Out: Send
+{
+    /// Target size in bytes of each parsed range.  Defaults to 16 MiB.
+    pub fn chunk_size(mut self, bytes: u64) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Sets how lines are split into target, weight, qid and features.
+    /// Must be called before the first row is read.
+    pub fn options<Q2>(self, opts: ParseOptions<Q2>) -> ParallelReader<TR, P, Q2>
+        where Q2: QidReader + Send + Sync + 'static, Q2::Out: Send
+    {
+        assert!(self.dispatched == 0, "options set after reading started");
+        let (tx, rx) = channel();
+        ParallelReader {
+            path: self.path,
+            f: self.f,
+            len: self.len,
+            tr: self.tr,
+            p: self.p,
+            opts: Arc::new(opts),
+            chunk_size: self.chunk_size,
+            max_in_flight: self.max_in_flight,
+            ordered: self.ordered,
+            dispatched: 0,
+            split_at: 0,
+            in_flight: 0,
+            tx, rx,
+            pending: BTreeMap::new(),
+            next_id: 0,
+            lines_before: 0,
+            current: Vec::new().into_iter()
+        }
+    }
+
+    /// Yields rows in completion order rather than file order
+    pub fn unordered(mut self) -> Self {
+        self.ordered = false;
+        self
+    }
+
+    // Finds the end of the range starting at `start`: the byte after the
+    // first newline at or past `start + chunk_size`.
+    fn find_split(&mut self, start: u64) -> io::Result<u64> {
+        let mut pos = start + self.chunk_size;
+        if pos >= self.len { return Ok(self.len) }
+        self.f.seek(SeekFrom::Start(pos))?;
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = self.f.read(&mut buf)?;
+            if n == 0 { return Ok(self.len) }
+            if let Some(i) = buf[..n].iter().position(|&b| b == b'\n') {
+                return Ok(pos + i as u64 + 1)
+            }
+            pos += n as u64;
+        }
+    }
+
+    fn dispatch(&mut self) -> io::Result<()> {
+        while self.in_flight < self.max_in_flight && self.split_at < self.len {
+            let start = self.split_at;
+            let end = self.find_split(start)?;
+            self.split_at = end;
+
+            let id = self.dispatched;
+            let path = self.path.clone();
+            let (tr, p, tx) = (self.tr.clone(), self.p.clone(), self.tx.clone());
+            let opts = self.opts.clone();
+            rayon::spawn(move || {
+                let chunk = parse_range(&path, start, end, &*tr, &*p, &opts);
+                // The reader may have been dropped; nobody is left to tell
+                let _ = tx.send(Chunk { id, lines: chunk.0, rows: chunk.1 });
+            });
+            self.dispatched += 1;
+            self.in_flight += 1;
+        }
+        Ok(())
+    }
+
+    fn next_chunk(&mut self) -> Option<Result<Batch<TR, P, Q>,ParseError>> {
+        if let Err(e) = self.dispatch() {
+            self.split_at = self.len;
+            return Some(Err(e.into()))
+        }
+        loop {
+            if self.ordered {
+                if let Some(chunk) = self.pending.remove(&self.next_id) {
+                    self.next_id += 1;
+                    return Some(Ok(chunk))
+                }
+            }
+            if self.in_flight == 0 { return None }
+            let chunk = self.rx.recv().expect("reader holds a sender");
+            self.in_flight -= 1;
+            if !self.ordered { return Some(Ok(chunk)) }
+            self.pending.insert(chunk.id, chunk);
+        }
+    }
+}
+
+impl <TR, P, Q> Iterator for ParallelReader<TR, P, Q>
+    where TR: TargetReader + Send + Sync + 'static, TR::Out: Send,
+          P: DataParse + Send + Sync + 'static, P::Out: Send,
+          Q: QidReader + Send + Sync + 'static, Q::Out: Send
+{
+    type Item = Item<TR, P, Q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.current.next() {
+                return Some(row)
+            }
+            let mut chunk = match self.next_chunk()? {
+                Ok(chunk) => chunk,
+                Err(e) => return Some(Err(e))
+            };
+            for e in chunk.rows.iter_mut().filter_map(|r| r.as_mut().err()) {
+                e.line = if self.ordered { e.line + self.lines_before } else { 0 };
+            }
+            self.lines_before += chunk.lines;
+            self.current = chunk.rows.into_iter();
+        }
+    }
+}
+
+// Parses the lines in [start, end), returning the number of lines seen.
+// Error line numbers are relative to the start of the range.
+fn parse_range<TR: TargetReader, P: DataParse, Q: QidReader>(path: &Path, start: u64, end: u64, tr: &TR, p: &P, opts: &ParseOptions<Q>)
+    -> (usize, Vec<Item<TR, P, Q>>)
+{
+    let mut buf = Vec::with_capacity((end - start) as usize);
+    let read = File::open(path).and_then(|mut f| {
+        f.seek(SeekFrom::Start(start))?;
+        f.take(end - start).read_to_end(&mut buf)
+    });
+    if let Err(e) = read {
+        return (0, vec![Err(ParseError::from(e).at(1, start))])
+    }
+
+    let mut rows = Vec::new();
+    let mut offset = start;
+    let mut lines = 0;
+    for raw in buf.split_inclusive(|&b| b == b'\n') {
+        lines += 1;
+        let line_start = offset;
+        offset += raw.len() as u64;
+        let line = match ::std::str::from_utf8(raw) {
+            Ok(l) => l.trim_end_matches(['\n', '\r']),
+            Err(e) => {
+                let e = io::Error::new(io::ErrorKind::InvalidData, e);
+                rows.push(Err(ParseError::new(ErrorKind::Io(e), "").at(lines, line_start)));
+                continue
+            }
+        };
+        if is_blank(line) { continue }
+        rows.push(parse_line_with(tr, p, opts, line).map_err(|e| e.at(lines, line_start)));
+    }
+    (lines, rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use types::SparseData;
+    use super::super::DisjointClassification;
+
+    fn write_file(name: &str, rows: usize) -> PathBuf {
+        let path = ::std::env::temp_dir().join(name);
+        let mut f = File::create(&path).unwrap();
+        for i in 0..rows {
+            if i == 500 {
+                writeln!(f, "{} 0:bad", i).unwrap();
+            } else {
+                writeln!(f, "{} {}:1 # row", i, i % 7).unwrap();
+            }
+        }
+        path
+    }
+
+    #[test]
+    fn ordered_matches_sequential() {
+        let path = write_file("svmloader_parallel_ordered.svm", 2000);
+        let rows: Vec<_> = load_parallel(path.to_str().unwrap(), DisjointClassification, SparseData::new(7))
+            .unwrap().chunk_size(100).collect();
+        assert_eq!(rows.len(), 2000);
+        for (i, r) in rows.iter().enumerate() {
+            match *r {
+                Ok(ref row) => assert_eq!(row.y, i),
+                Err(ref e) => assert_eq!((i, e.line), (500, 501))
+            }
+        }
+    }
+
+    #[test]
+    fn unordered_sees_every_row() {
+        let path = write_file("svmloader_parallel_unordered.svm", 2000);
+        let mut ys: Vec<_> = load_parallel(path.to_str().unwrap(), DisjointClassification, SparseData::new(7))
+            .unwrap().chunk_size(100).unordered()
+            .filter_map(|r| r.ok()).map(|r| r.y).collect();
+        ys.sort();
+        assert_eq!(ys.len(), 1999);
+        assert_eq!(ys[500], 501);
+    }
+}
@@ -0,0 +1,148 @@
+//! Readers for the value of `qid:` tokens
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// Turns the text after `qid:` into a query id.  Returning `None` rejects
+/// the line with `ErrorKind::BadQid`.
+pub trait QidReader {
+    type Out: Debug;
+
+    fn process(&self, data: &str) -> Option<Self::Out>;
+}
+
+impl <Q: QidReader + ?Sized> QidReader for &Q {
+    type Out = Q::Out;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        (**self).process(data)
+    }
+}
+
+/// Non-negative integer ids as `usize`; the default
+#[derive(Debug,Clone,Copy,Default)]
+pub struct NumericQid;
+
+impl QidReader for NumericQid {
+    type Out = usize;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        data.parse().ok()
+    }
+}
+
+/// Hashed or otherwise 64-bit ids, whatever the platform's `usize`
+#[derive(Debug,Clone,Copy,Default)]
+pub struct U64Qid;
+
+impl QidReader for U64Qid {
+    type Out = u64;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        data.parse().ok()
+    }
+}
+
+/// Ids kept verbatim, for alphanumeric query ids
+#[derive(Debug,Clone,Copy,Default)]
+pub struct StringQid;
+
+impl QidReader for StringQid {
+    type Out = String;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        if data.is_empty() { None } else { Some(data.to_owned()) }
+    }
+}
+
+/// Maps each distinct id string to a dense `usize`, numbered from 0 in order
+/// of first appearance.  Share one interner between readers by reference.
+#[derive(Debug,Default)]
+pub struct InternedQid {
+    table: Mutex<Interner>
+}
+
+#[derive(Debug,Default)]
+struct Interner {
+    ids: HashMap<String,usize>,
+    names: Vec<String>
+}
+
+impl InternedQid {
+    pub fn new() -> Self {
+        InternedQid::default()
+    }
+
+    /// Number of distinct ids seen
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The id given to `name`, if it has been seen
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.table.lock().unwrap().ids.get(name).cloned()
+    }
+
+    /// The original text of `id`
+    pub fn name(&self, id: usize) -> Option<String> {
+        self.table.lock().unwrap().names.get(id).cloned()
+    }
+
+    /// All names, indexed by id
+    pub fn names(&self) -> Vec<String> {
+        self.table.lock().unwrap().names.clone()
+    }
+}
+
+impl QidReader for InternedQid {
+    type Out = usize;
+
+    fn process(&self, data: &str) -> Option<Self::Out> {
+        if data.is_empty() { return None }
+        let mut t = self.table.lock().unwrap();
+        if let Some(&id) = t.ids.get(data) {
+            return Some(id)
+        }
+        let id = t.names.len();
+        t.ids.insert(data.to_owned(), id);
+        t.names.push(data.to_owned());
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::ParseOptions;
+    use types::SparseData;
+    use super::super::{from_str,parse_line_with,Regression};
+
+    #[test]
+    fn qid_types() {
+        let sd = SparseData::new(2);
+        let big = ParseOptions::new().qids(U64Qid);
+        let row = parse_line_with(&Regression, &sd, &big, "1 qid:18446744073709551615 0:1").unwrap();
+        assert_eq!(row.qid, Some(u64::MAX));
+
+        let s = ParseOptions::new().qids(StringQid);
+        let row = parse_line_with(&Regression, &sd, &s, "1 qid:q-7a 0:1").unwrap();
+        assert_eq!(row.qid, Some("q-7a".to_owned()));
+        assert!(parse_line_with(&Regression, &sd, &s, "1 qid: 0:1").is_err());
+        assert!(parse_line_with(&Regression, &sd, &ParseOptions::new(), "1 qid:q-7a 0:1").is_err());
+    }
+
+    #[test]
+    fn interned_ids() {
+        let interner = InternedQid::new();
+        let qids: Vec<_> = from_str("1 qid:b 0:1\n2 qid:a 0:1\n3 qid:b 1:1\n4 1:1\n", Regression, SparseData::new(2))
+            .options(ParseOptions::new().qids(&interner))
+            .map(|r| r.unwrap().qid).collect();
+        assert_eq!(qids, vec![Some(0), Some(1), Some(0), None]);
+        assert_eq!(interner.names(), vec!["b", "a"]);
+        assert_eq!(interner.id("a"), Some(1));
+    }
+}
@@ -0,0 +1,131 @@
+//! A quick pass over a file to learn its shape before loading it
+use std::io::BufRead;
+
+use error::{ErrorKind,ParseError};
+use options::ParseOptions;
+use qid::QidReader;
+use types::{DataParse,IndexBase};
+use super::{compression,from_reader,TargetReader};
+
+/// What a scan found
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct Scan {
+    /// Number of data rows
+    pub rows: usize,
+    /// Smallest feature index, if any row had features
+    pub min_index: Option<usize>,
+    /// Largest feature index, if any row had features
+    pub max_index: Option<usize>
+}
+
+impl Scan {
+    /// Width needed to hold every index seen
+    pub fn dims(&self) -> usize {
+        self.max_index.map_or(0, |i| i + 1)
+    }
+
+    /// Width needed once indices are shifted by `base`
+    pub fn dims_for(&self, base: IndexBase) -> usize {
+        self.dims().saturating_sub(base.offset())
+    }
+
+    /// Guesses the numbering: a file is 0-based only if index 0 appears
+    pub fn index_base(&self) -> IndexBase {
+        match self.min_index {
+            Some(0) | None => IndexBase::Zero,
+            Some(_) => IndexBase::One
+        }
+    }
+}
+
+// Accepts any target; a scan only looks at the features
+struct AnyTarget;
+
+impl TargetReader for AnyTarget {
+    type Out = ();
+
+    fn process(&self, _data: &str) -> Option<Self::Out> {
+        Some(())
+    }
+}
+
+// Reads only the index half of each `idx:value` token
+struct IndexRange;
+
+impl DataParse for IndexRange {
+    type Out = Option<(usize, usize)>;
+
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut range = None;
+        for x in xs {
+            let idx: usize = x.split(':').next()
+                .and_then(|idx| idx.parse().ok())
+                .ok_or_else(|| ParseError::new(ErrorKind::BadIndex, x))?;
+            range = Some(range.map_or((idx, idx), |(lo, hi): (usize, usize)| (lo.min(idx), hi.max(idx))));
+        }
+        Ok(range)
+    }
+}
+
+/// Scans a source, stopping at the first malformed line
+pub fn scan<R: BufRead>(br: R) -> Result<Scan,ParseError> {
+    scan_with(br, &ParseOptions::default())
+}
+
+/// Scans a source whose lines are split according to `opts`.  Features
+/// must still be numeric indices, so VW files need `Namespaces::Flatten`.
+pub fn scan_with<R: BufRead, Q: QidReader>(br: R, opts: &ParseOptions<Q>) -> Result<Scan,ParseError> {
+    let mut s = Scan::default();
+    for row in from_reader(br, AnyTarget, IndexRange).options(opts.by_ref()) {
+        s.rows += 1;
+        if let Some((lo, hi)) = row?.x {
+            s.min_index = Some(s.min_index.map_or(lo, |m| m.min(lo)));
+            s.max_index = s.max_index.max(Some(hi));
+        }
+    }
+    Ok(s)
+}
+
+/// Scans a file, decompressing it if needed
+pub fn scan_file(fname: &str) -> Result<Scan,ParseError> {
+    scan_file_with(fname, &ParseOptions::default())
+}
+
+/// `scan_file` according to `opts`
+pub fn scan_file_with<Q: QidReader>(fname: &str, opts: &ParseOptions<Q>) -> Result<Scan,ParseError> {
+    scan_with(compression::open(fname)?, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::{Format,Namespaces,TargetWeight};
+
+    #[test]
+    fn scan_dims() {
+        let s = scan(&b"1 qid:3 0:1 17:2\n# c\n-1 4:1\n 2:5\n"[..]).unwrap();
+        assert_eq!(s, Scan { rows: 3, min_index: Some(0), max_index: Some(17) });
+        assert_eq!(s.dims(), 18);
+        assert_eq!(s.index_base(), IndexBase::Zero);
+
+        let s = scan(&b"1 1:1 5:2\n-1 3:1\n"[..]).unwrap();
+        assert_eq!(s.index_base(), IndexBase::One);
+        assert_eq!(s.dims_for(s.index_base()), 5);
+
+        let e = scan(&b"1 0:1\n1 a:1\n"[..]).err().unwrap();
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn scan_with_options() {
+        let weighted = &b"1 0.5 3:1\n-1 2 sid:a 1:1\n"[..];
+        assert!(scan(weighted).is_err());
+        let opts = ParseOptions::new().weights(TargetWeight::Column);
+        let s = scan_with(weighted, &opts).unwrap();
+        assert_eq!(s, Scan { rows: 2, min_index: Some(1), max_index: Some(3) });
+
+        let vw = ParseOptions::new().format(Format::Vw).namespaces(Namespaces::Flatten);
+        let s = scan_with(&b"1 |a 4 |b 7:2\n"[..], &vw).unwrap();
+        assert_eq!((s.min_index, s.max_index), (Some(4), Some(7)));
+    }
+}
@@ -1,8 +1,11 @@
+//! Defines datastypes
 use std::fmt::Debug;
-/// Defines datastypes
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+use error::{ErrorKind,ParseError};
 
 /// Sparse datatype
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,Default)]
 pub struct Sparse(pub usize, pub Vec<usize>, pub Vec<f32>);
 
 impl Sparse {
@@ -15,51 +18,400 @@ impl Sparse {
     }
 }
 
+/// Parses an `f32`, taking a fast exact path for short decimals such as
+/// `3`, `-0.25` or `1.125` and deferring to the std parser otherwise.
+pub fn parse_f32(s: &str) -> Option<f32> {
+    const POW10: [f32; 11] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+    let b = s.as_bytes();
+    let (neg, digits) = match b.first() {
+        Some(&b'-') => (true, &b[1..]),
+        Some(&b'+') => (false, &b[1..]),
+        _ => (false, b)
+    };
+
+    // Both the mantissa and the power of ten are exact in an f32, so the
+    // division is correctly rounded, matching the std parser.
+    let mut m = 0u32;
+    let mut frac = 0;
+    let mut seen_dot = false;
+    let mut nd = 0;
+    for &c in digits {
+        match c {
+            b'0'..=b'9' => {
+                m = m * 10 + u32::from(c - b'0');
+                if m >= 1 << 24 { return s.parse().ok() }
+                nd += 1;
+                if seen_dot { frac += 1; }
+            },
+            b'.' if !seen_dot => seen_dot = true,
+            _ => return s.parse().ok()
+        }
+    }
+    if nd == 0 || frac >= POW10.len() { return s.parse().ok() }
+    let v = m as f32 / POW10[frac];
+    Some(if neg { -v } else { v })
+}
+
+// Splits a named feature at its last `:`; a bare name has value 1
+pub(crate) fn named_feature(x: &str) -> Result<(&str, f32),ParseError> {
+    let (name, v) = match x.rfind(':') {
+        Some(i) => {
+            let v = parse_f32(&x[i + 1..]).ok_or_else(|| ParseError::new(ErrorKind::BadValue, x))?;
+            (&x[..i], v)
+        },
+        None => (x, 1.0)
+    };
+    if name.is_empty() {
+        return Err(ParseError::new(ErrorKind::BadIndex, x))
+    }
+    Ok((name, v))
+}
+
+// Sorts by index, sums repeats and moves the non-zero features into `out`
+pub(crate) fn fill_summed(mut iv: Vec<(usize, f32)>, dims: usize, out: &mut Sparse) {
+    iv.sort_by_key(|x| x.0);
+    iv.dedup_by(|cur, prev| {
+        if cur.0 != prev.0 { return false }
+        prev.1 += cur.1;
+        true
+    });
+    out.0 = dims;
+    out.1.clear();
+    out.2.clear();
+    for (i, v) in iv {
+        if v != 0.0 {
+            out.1.push(i);
+            out.2.push(v);
+        }
+    }
+}
+
 pub trait DataParse {
     type Out: Debug;
 
-    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Option<Self::Out>;
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError>;
+
+    /// Parses into an existing value, reusing its buffers where possible
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        *out = self.parse(xs)?;
+        Ok(())
+    }
+}
+
+impl <D: DataParse + ?Sized> DataParse for &D {
+    type Out = D::Out;
+
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        (**self).parse(xs)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        (**self).parse_into(xs, out)
+    }
+}
+
+/// Whether the first feature in a file is numbered 0 or 1.  LIBSVM files
+/// are conventionally 1-based; rows always hold 0-based indices.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum IndexBase {
+    #[default]
+    Zero,
+    One
+}
+
+impl IndexBase {
+    /// Amount subtracted from file indices on read and added on write
+    pub fn offset(self) -> usize {
+        match self {
+            IndexBase::Zero => 0,
+            IndexBase::One  => 1
+        }
+    }
+}
+
+/// Parses features positionally into a `Vec<f32>`; any `idx:` prefix is
+/// ignored on read.  The index base only affects how indices are written.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct DenseData {
+    base: IndexBase
 }
 
-#[derive(Debug,Clone,PartialEq,Eq)]
-pub struct DenseData;
+impl DenseData {
+    pub fn new() -> Self {
+        DenseData::default()
+    }
+
+    pub fn index_base(mut self, base: IndexBase) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn base(&self) -> IndexBase {
+        self.base
+    }
+}
 
 impl DataParse for DenseData {
     type Out = Vec<f32>;
 
-    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Option<Self::Out> {
-        xs.map(|x| {
-            x.split(':').last().and_then(|x| x.parse().ok())
-        }).collect()
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut out = Vec::new();
+        self.parse_into(xs, &mut out)?;
+        Ok(out)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        out.clear();
+        for x in xs {
+            let v = x.split(':').next_back().and_then(parse_f32)
+                .ok_or_else(|| ParseError::new(ErrorKind::BadValue, x))?;
+            out.push(v);
+        }
+        Ok(())
+    }
+}
+
+/// How repeated indices within a row are combined
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Duplicates {
+    /// Keep the first value
+    #[default]
+    First,
+    /// Keep the last value
+    Last,
+    /// Add the values together
+    Sum,
+    /// Keep the largest value
+    Max
+}
+
+/// What happens to out-of-range and duplicate indices
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Validation {
+    /// Fix them up as configured and count them in `SparseData::stats`
+    #[default]
+    Count,
+    /// Reject the row with `ErrorKind::IndexOutOfRange` or
+    /// `ErrorKind::DuplicateIndex`
+    Error
+}
+
+/// Counts of index problems fixed up while parsing
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct IndexStats {
+    /// Features dropped because their index was at or past the dimension
+    pub out_of_range: usize,
+    /// Features merged into an earlier feature with the same index
+    pub duplicates: usize
+}
+
+/// Parses `idx:value` features into a `Sparse` vector.
+///
+/// With a fixed dimension, indices at or above it are dropped.  An inferred
+/// parser keeps every index and grows its width as rows are read: each row
+/// gets the widest dimension seen so far, and `dims` reports the final width
+/// once the input has been consumed.
+#[derive(Debug)]
+pub struct SparseData {
+    fixed: Option<usize>,
+    seen: AtomicUsize,
+    base: IndexBase,
+    duplicates: Duplicates,
+    validation: Validation,
+    out_of_range_count: AtomicUsize,
+    duplicate_count: AtomicUsize
+}
+
+impl SparseData {
+    pub fn new(dims: usize) -> Self {
+        SparseData::with_dims(Some(dims))
+    }
+
+    /// Infers the dimension from the indices read
+    pub fn infer() -> Self {
+        SparseData::with_dims(None)
+    }
+
+    fn with_dims(fixed: Option<usize>) -> Self {
+        SparseData {
+            fixed,
+            seen: AtomicUsize::new(fixed.unwrap_or(0)),
+            base: IndexBase::default(),
+            duplicates: Duplicates::default(),
+            validation: Validation::default(),
+            out_of_range_count: AtomicUsize::new(0),
+            duplicate_count: AtomicUsize::new(0)
+        }
+    }
+
+    /// Sets the numbering of indices in the file.  Defaults to
+    /// `IndexBase::Zero`; with `IndexBase::One`, file index 1 is read as 0
+    /// and index 0 is rejected.
+    pub fn index_base(mut self, base: IndexBase) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn base(&self) -> IndexBase {
+        self.base
+    }
+
+    /// Sets how repeated indices are merged.  Defaults to `Duplicates::First`.
+    pub fn duplicates(mut self, d: Duplicates) -> Self {
+        self.duplicates = d;
+        self
+    }
+
+    /// Sets whether bad indices are counted or rejected.  Defaults to
+    /// `Validation::Count`.
+    pub fn validation(mut self, v: Validation) -> Self {
+        self.validation = v;
+        self
+    }
+
+    /// The fixed dimension, or one past the largest index read so far
+    pub fn dims(&self) -> usize {
+        self.seen.load(Ordering::Relaxed)
+    }
+
+    pub fn is_inferred(&self) -> bool {
+        self.fixed.is_none()
+    }
+
+    // Everything but the width and counts gathered while reading
+    pub(crate) fn settings(&self) -> (Option<usize>, IndexBase, Duplicates, Validation) {
+        (self.fixed, self.base, self.duplicates, self.validation)
+    }
+
+    /// Index problems counted so far
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            out_of_range: self.out_of_range_count.load(Ordering::Relaxed),
+            duplicates: self.duplicate_count.load(Ordering::Relaxed)
+        }
+    }
+
+    // Sorts by index and merges repeats, returning the first repeated index
+    fn merge(&self, iv: &mut Vec<(usize,f32)>) -> Option<usize> {
+        iv.sort_by_key(|x| x.0);
+        let mut first = None;
+        let strategy = self.duplicates;
+        iv.dedup_by(|cur, prev| {
+            if cur.0 != prev.0 { return false }
+            first = first.or(Some(cur.0));
+            match strategy {
+                Duplicates::First => (),
+                Duplicates::Last  => prev.1 = cur.1,
+                Duplicates::Sum   => prev.1 += cur.1,
+                Duplicates::Max   => prev.1 = prev.1.max(cur.1)
+            }
+            true
+        });
+        first
+    }
+}
+
+impl Clone for SparseData {
+    fn clone(&self) -> Self {
+        let stats = self.stats();
+        SparseData {
+            fixed: self.fixed,
+            seen: AtomicUsize::new(self.dims()),
+            base: self.base,
+            duplicates: self.duplicates,
+            validation: self.validation,
+            out_of_range_count: AtomicUsize::new(stats.out_of_range),
+            duplicate_count: AtomicUsize::new(stats.duplicates)
+        }
+    }
+}
+
+impl PartialEq for SparseData {
+    fn eq(&self, other: &SparseData) -> bool {
+        self.settings() == other.settings()
     }
 }
 
-#[derive(Debug,Clone,PartialEq,Eq)]
-pub struct SparseData(pub usize);
+impl Eq for SparseData {}
 
 impl DataParse for SparseData {
     type Out = Sparse;
 
-    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Option<Self::Out> {
-        let ivs: Option<Vec<(usize,f32)>> = xs.map(|x| {
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut out = Sparse::default();
+        self.parse_into(xs, &mut out)?;
+        Ok(out)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        out.1.clear();
+        out.2.clear();
+        let mut sorted = true;
+        let offset = self.base.offset();
+        for x in xs {
             let mut p = x.split(':');
-            let idx: Option<usize> = p.next()
-                .and_then(|idx| idx.parse().ok());
-            let v: Option<f32> = p.next()
-                .and_then(|val| val.parse().ok());
+            let idx: usize = p.next()
+                .and_then(|idx| idx.parse::<usize>().ok())
+                .and_then(|idx| idx.checked_sub(offset))
+                .ok_or_else(|| ParseError::new(ErrorKind::BadIndex, x))?;
+            let v: f32 = p.next()
+                .and_then(parse_f32)
+                .ok_or_else(|| ParseError::new(ErrorKind::BadValue, x))?;
 
-            idx.and_then(|i| v.map(|vi| (i, vi)))
-        }).collect();
+            sorted = sorted && out.1.last().is_none_or(|&last| last < idx);
+            out.1.push(idx);
+            out.2.push(v);
+        }
+
+        if !sorted {
+            // Well-formed files never get here
+            let mut iv: Vec<(usize,f32)> = out.1.drain(..).zip(out.2.drain(..)).collect();
+            let before = iv.len();
+            if let Some(idx) = self.merge(&mut iv) {
+                if self.validation == Validation::Error {
+                    return Err(ParseError::new(ErrorKind::DuplicateIndex, (idx + offset).to_string()))
+                }
+                self.duplicate_count.fetch_add(before - iv.len(), Ordering::Relaxed);
+            }
+            for (i, v) in iv {
+                out.1.push(i);
+                out.2.push(v);
+            }
+        }
 
-        ivs.map(|mut iv| {
-            // Sort then dedup by key
-            iv.sort_by_key(|x| x.0);
-            iv.dedup_by_key(|x| x.0);
-            let (is, vs) = iv.into_iter()
-                .filter(|x| x.0 < self.0 && x.1 != 0.0).unzip();
+        let dims = match self.fixed {
+            Some(d) => d,
+            None => {
+                let width = out.1.last().map_or(0, |&i| i + 1);
+                self.seen.fetch_max(width, Ordering::Relaxed).max(width)
+            }
+        };
+        out.0 = dims;
 
-            Sparse(self.0, is, vs)
-        })
+        // Indices are sorted, so everything out of range sits at the end
+        let in_range = out.1.iter().position(|&i| i >= dims).unwrap_or(out.1.len());
+        let dropped = out.1.len() - in_range;
+        if dropped > 0 {
+            if self.validation == Validation::Error {
+                return Err(ParseError::new(ErrorKind::IndexOutOfRange, (out.1[in_range] + offset).to_string()))
+            }
+            self.out_of_range_count.fetch_add(dropped, Ordering::Relaxed);
+            out.1.truncate(in_range);
+            out.2.truncate(in_range);
+        }
+
+        // Drop explicit zeros in place
+        let mut kept = 0;
+        for j in 0..out.1.len() {
+            if out.2[j] != 0.0 {
+                out.1[kept] = out.1[j];
+                out.2[kept] = out.2[j];
+                kept += 1;
+            }
+        }
+        out.1.truncate(kept);
+        out.2.truncate(kept);
+        Ok(())
     }
 }
 
@@ -77,3 +429,86 @@ impl Dimension for Sparse {
     type Out = usize;
     fn dims(&self) -> Self::Out { self.0 }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fast_floats_match_std() {
+        for s in &["0", "-0", "1", "+2", "3.25", "-0.1", "0.3", ".5", "1.", "16777215",
+                   "16777217", "0.00000000001", "1e5", "-2.5E-3", "nan", "inf", "123456.789"] {
+            let ours = parse_f32(s);
+            let std: Option<f32> = s.parse().ok();
+            assert_eq!(ours.map(f32::to_bits), std.map(f32::to_bits), "{}", s);
+        }
+        assert_eq!(parse_f32("."), None);
+        assert_eq!(parse_f32("1.2.3"), None);
+        assert_eq!(parse_f32(""), None);
+    }
+
+    #[test]
+    fn sparse_parse_into_reuses_buffers() {
+        let sd = SparseData::new(5);
+        let mut out = Sparse::default();
+        sd.parse_into("1:1 3:2 7:1".split(' '), &mut out).unwrap();
+        assert_eq!((out.1.clone(), out.2.clone()), (vec![1, 3], vec![1.0, 2.0]));
+
+        let cap = out.1.capacity();
+        sd.parse_into("4:1 0:2 4:3 2:0".split(' '), &mut out).unwrap();
+        assert_eq!((out.1.clone(), out.2.clone()), (vec![0, 4], vec![2.0, 1.0]));
+        assert_eq!(out.1.capacity(), cap);
+    }
+
+    #[test]
+    fn sparse_infers_dims() {
+        let sd = SparseData::infer();
+        let a = sd.parse("1:1 3:2".split(' ')).unwrap();
+        let b = sd.parse("9:1".split(' ')).unwrap();
+        let c = sd.parse("0:1".split(' ')).unwrap();
+        assert_eq!((a.0, b.0, c.0), (4, 10, 10));
+        assert_eq!(b.1, vec![9]);
+        assert_eq!(sd.dims(), 10);
+    }
+
+    #[test]
+    fn sparse_duplicates_and_range() {
+        let xs = || "3:1 1:2 3:4 9:1 1:-1".split(' ');
+        let merged = |d| {
+            let sd = SparseData::new(5).duplicates(d);
+            let s = sd.parse(xs()).unwrap();
+            (s.1, s.2, sd.stats())
+        };
+        let stats = IndexStats { out_of_range: 1, duplicates: 2 };
+        assert_eq!(merged(Duplicates::First), (vec![1, 3], vec![2.0, 1.0], stats));
+        assert_eq!(merged(Duplicates::Last), (vec![1, 3], vec![-1.0, 4.0], stats));
+        assert_eq!(merged(Duplicates::Sum), (vec![1, 3], vec![1.0, 5.0], stats));
+        assert_eq!(merged(Duplicates::Max), (vec![1, 3], vec![2.0, 4.0], stats));
+
+        let strict = SparseData::new(5).validation(Validation::Error);
+        let e = strict.parse(xs()).err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::DuplicateIndex));
+        assert_eq!(e.token, "1");
+        let e = strict.parse("1:1 7:1".split(' ')).err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::IndexOutOfRange));
+        assert_eq!(e.token, "7");
+    }
+
+    #[test]
+    fn one_based_indices() {
+        let sd = SparseData::new(3).index_base(IndexBase::One);
+        let s = sd.parse("1:1 3:2".split(' ')).unwrap();
+        assert_eq!(s.1, vec![0, 2]);
+        let e = sd.parse("0:1".split(' ')).err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::BadIndex));
+
+        // Errors name the index as written in the file
+        let strict = SparseData::new(4).index_base(IndexBase::One).validation(Validation::Error);
+        let e = strict.parse("1:1 5:1".split(' ')).err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::IndexOutOfRange));
+        assert_eq!(e.token, "5");
+        let e = strict.parse("2:1 1:1 2:3".split(' ')).err().unwrap();
+        assert!(matches!(e.kind, ErrorKind::DuplicateIndex));
+        assert_eq!(e.token, "2");
+    }
+}
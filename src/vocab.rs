@@ -0,0 +1,197 @@
+//! Named features mapped to indices through a vocabulary
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self,BufRead,BufReader,BufWriter,Write};
+use std::sync::Mutex;
+
+use error::{ErrorKind,ParseError};
+use types::{fill_summed,named_feature,DataParse,Sparse};
+
+/// What a frozen vocabulary does with a name it has not seen
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Oov {
+    /// Leave the feature out
+    #[default]
+    Drop,
+    /// Reject the row with `ErrorKind::UnknownFeature`
+    Error,
+    /// Send every unknown name to one extra index, `len()`
+    Bucket
+}
+
+#[derive(Debug,Default)]
+struct Table {
+    ids: HashMap<String,usize>,
+    names: Vec<String>,
+    frozen: bool
+}
+
+impl Table {
+    fn width(&self, oov: Oov) -> usize {
+        self.names.len() + (self.frozen && oov == Oov::Bucket) as usize
+    }
+}
+
+/// Parses `name:value` features, giving each new name the next free index.
+///
+/// Tokens are split like `HashedSparseData`'s.  While growing, each row gets
+/// the vocabulary size so far as its dimension.  Once frozen the vocabulary
+/// no longer changes and unknown names follow the `Oov` policy, so a
+/// vocabulary built on a training set can be saved, loaded and applied to
+/// the test set.  Share one vocabulary between readers by reference.
+#[derive(Debug,Default)]
+pub struct Vocabulary {
+    table: Mutex<Table>,
+    oov: Oov
+}
+
+impl Vocabulary {
+    /// An empty vocabulary that grows as names are read
+    pub fn new() -> Self {
+        Vocabulary::default()
+    }
+
+    /// Sets the policy for unknown names once frozen.  Defaults to `Oov::Drop`.
+    pub fn oov(mut self, oov: Oov) -> Self {
+        self.oov = oov;
+        self
+    }
+
+    pub(crate) fn oov_policy(&self) -> Oov {
+        self.oov
+    }
+
+    /// Stops the vocabulary from growing
+    pub fn freeze(&self) {
+        self.table.lock().unwrap().frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.table.lock().unwrap().frozen
+    }
+
+    /// Number of known names
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Width of the vectors produced: the known names plus the OOV bucket
+    pub fn dims(&self) -> usize {
+        self.table.lock().unwrap().width(self.oov)
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.table.lock().unwrap().ids.get(name).cloned()
+    }
+
+    pub fn name(&self, idx: usize) -> Option<String> {
+        self.table.lock().unwrap().names.get(idx).cloned()
+    }
+
+    /// All names, indexed by position
+    pub fn names(&self) -> Vec<String> {
+        self.table.lock().unwrap().names.clone()
+    }
+
+    /// Writes the names one per line, in index order
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for name in &self.table.lock().unwrap().names {
+            writeln!(w, "{}", name)?;
+        }
+        w.flush()
+    }
+
+    pub fn save(&self, fname: &str) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(fname)?))
+    }
+
+    /// Reads names written by `write_to`.  The result is frozen.
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Self> {
+        let mut t = Table { frozen: true, ..Table::default() };
+        for line in r.lines() {
+            let name = line?;
+            if name.is_empty() || t.ids.contains_key(&name) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("bad vocabulary entry: {:?}", name)))
+            }
+            t.ids.insert(name.clone(), t.names.len());
+            t.names.push(name);
+        }
+        Ok(Vocabulary { table: Mutex::new(t), oov: Oov::default() })
+    }
+
+    pub fn load(fname: &str) -> io::Result<Self> {
+        Vocabulary::read_from(BufReader::new(File::open(fname)?))
+    }
+}
+
+impl DataParse for Vocabulary {
+    type Out = Sparse;
+
+    fn parse<'a, I: Iterator<Item=&'a str>>(&self, xs: I) -> Result<Self::Out,ParseError> {
+        let mut out = Sparse::default();
+        self.parse_into(xs, &mut out)?;
+        Ok(out)
+    }
+
+    fn parse_into<'a, I: Iterator<Item=&'a str>>(&self, xs: I, out: &mut Self::Out) -> Result<(),ParseError> {
+        let mut iv = Vec::new();
+        let mut t = self.table.lock().unwrap();
+        for x in xs {
+            let (name, v) = named_feature(x)?;
+            let idx = match t.ids.get(name) {
+                Some(&idx) => idx,
+                None if !t.frozen => {
+                    let idx = t.names.len();
+                    t.ids.insert(name.to_owned(), idx);
+                    t.names.push(name.to_owned());
+                    idx
+                },
+                None => match self.oov {
+                    Oov::Drop => continue,
+                    Oov::Error => return Err(ParseError::new(ErrorKind::UnknownFeature, x)),
+                    Oov::Bucket => t.names.len()
+                }
+            };
+            iv.push((idx, v));
+        }
+        fill_summed(iv, t.width(self.oov), out);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{from_str,Regression};
+
+    #[test]
+    fn grow_freeze_and_reload() {
+        let vocab = Vocabulary::new();
+        let rows: Vec<_> = from_str("1 b:1 a:2\n0 a:1 c\n", Regression, &vocab)
+            .map(|r| r.unwrap().x).collect();
+        assert_eq!(vocab.names(), vec!["b", "a", "c"]);
+        assert_eq!((rows[0].0, rows[1].0), (2, 3));
+        assert_eq!((&rows[1].1, &rows[1].2), (&vec![1, 2], &vec![1.0, 1.0]));
+
+        vocab.freeze();
+        let x = vocab.parse("d:1 a:3".split(' ')).unwrap();
+        assert_eq!((x.0, x.1, x.2), (3, vec![1], vec![3.0]));
+
+        let mut saved = Vec::new();
+        vocab.write_to(&mut saved).unwrap();
+        assert_eq!(saved, b"b\na\nc\n");
+        let test = Vocabulary::read_from(&saved[..]).unwrap().oov(Oov::Bucket);
+        assert!(test.is_frozen());
+        let x = test.parse("d:1 e:1 c:2".split(' ')).unwrap();
+        assert_eq!((x.0, x.1, x.2), (4, vec![2, 3], vec![2.0, 2.0]));
+
+        let strict = Vocabulary::read_from(&saved[..]).unwrap().oov(Oov::Error);
+        assert!(matches!(strict.parse("d:1".split(' ')).err().unwrap().kind, ErrorKind::UnknownFeature));
+        assert!(Vocabulary::read_from(&b"a\na\n"[..]).is_err());
+    }
+}
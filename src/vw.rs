@@ -0,0 +1,172 @@
+//! Vowpal Wabbit text format
+//!
+//! A line is a header and one or more namespaces:
+//!
+//! ```text
+//! label [importance [base]] ['tag] |ns[:scale] feat[:value] ... |ns2 ...
+//! ```
+//!
+//! Select it with `ParseOptions::format(Format::Vw)`.  The label goes to the
+//! `TargetReader`, the importance becomes the row weight, the tag (without
+//! its quote) becomes the comment, and the base is checked and ignored.  A
+//! tag need not be quoted when it touches the first `|`.  An unlabeled line
+//! such as ` |f a` passes an empty label, so it needs a `TargetReader` that
+//! accepts `""`, such as `MultiLabelClassification`.  Features are
+//! handed to the `DataParse` as `name:value` tokens with values multiplied
+//! by the namespace scale.  Per `Namespaces`, names are prefixed as
+//! `ns^name`, left bare, or grouped behind `|ns` tokens.  Named-feature parsers such as
+//! `HashedSparseData` and `Vocabulary` fit this directly.
+use std::fmt::Write;
+use std::str::SplitWhitespace;
+
+use error::{ErrorKind,ParseError};
+use options::{Namespaces,ParseOptions};
+use qid::QidReader;
+use types::parse_f32;
+use super::{Fields,IterCons,TargetReader};
+
+// Splits a VW line, writing the rewritten feature tokens into `buf`
+pub(crate) fn fields<'a, TR: TargetReader, Q: QidReader>(tr: &TR, opts: &ParseOptions<Q>, line: &'a str, buf: &'a mut String)
+    -> Result<Fields<'a, TR::Out, Q::Out>,ParseError>
+{
+    let mut parts = line.splitn(2, '|');
+    let header = parts.next().unwrap();
+    let body = parts.next();
+
+    // A tag is quoted, or bare when it touches the first `|`
+    let mut head: Vec<&str> = header.split_whitespace().collect();
+    let touches_bar = body.is_some() && !header.ends_with(char::is_whitespace);
+    let comment = match head.last() {
+        Some(t) if touches_bar || t.starts_with('\'') => {
+            let tag = t.strip_prefix('\'').unwrap_or(t);
+            head.pop();
+            Some(tag)
+        },
+        _ => None
+    };
+    if head.len() > 3 {
+        return Err(ParseError::new(ErrorKind::BadTarget, head[3]))
+    }
+    let label = head.first().cloned().unwrap_or("");
+    let y = tr.process(label).ok_or_else(|| ParseError::new(ErrorKind::BadTarget, label))?;
+    let weight = match head.get(1) {
+        Some(w) => Some(parse_f32(w).ok_or_else(|| ParseError::new(ErrorKind::BadWeight, *w))?),
+        None => None
+    };
+    if let Some(base) = head.get(2) {
+        parse_f32(base).ok_or_else(|| ParseError::new(ErrorKind::BadValue, *base))?;
+    }
+    let body = body.unwrap_or("");
+
+    buf.clear();
+    for ns in body.split('|') {
+        let (name, scale, features) = namespace(ns)?;
+        if opts.namespaces == Namespaces::Separate {
+            write!(buf, "|{} ", name).unwrap();
+        }
+        let prefix = if opts.namespaces == Namespaces::Prefix { name } else { "" };
+        for feat in features {
+            if !prefix.is_empty() {
+                buf.push_str(prefix);
+                buf.push('^');
+            }
+            match (scale, feat.rfind(':')) {
+                (None, Some(_)) => buf.push_str(feat),
+                (None, None) => write!(buf, "{}:1", feat).unwrap(),
+                (Some(s), None) => write!(buf, "{}:{}", feat, s).unwrap(),
+                (Some(s), Some(i)) => {
+                    let v = parse_f32(&feat[i + 1..])
+                        .ok_or_else(|| ParseError::new(ErrorKind::BadValue, feat))?;
+                    write!(buf, "{}:{}", &feat[..i], v * s).unwrap();
+                }
+            }
+            buf.push(' ');
+        }
+    }
+
+    let features = IterCons(None, buf.split_whitespace());
+    Ok(Fields { y, qid: None, weight, meta: Vec::new(), comment, features })
+}
+
+// Splits `ns[:scale] feats...` into the namespace name, its scale if any,
+// and the feature tokens.  A namespace starting with a space is unnamed.
+fn namespace(ns: &str) -> Result<(&str, Option<f32>, SplitWhitespace<'_>),ParseError> {
+    if ns.starts_with(char::is_whitespace) || ns.is_empty() {
+        return Ok(("", None, ns.split_whitespace()))
+    }
+    let mut tokens = ns.split_whitespace();
+    let first = tokens.next().unwrap();
+    match first.find(':') {
+        Some(i) => {
+            let s = parse_f32(&first[i + 1..]).ok_or_else(|| ParseError::new(ErrorKind::BadValue, first))?;
+            Ok((&first[..i], Some(s), tokens))
+        },
+        None => Ok((first, None, tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use options::{Format,Namespaces,ParseOptions};
+    use types::SparseData;
+    use vocab::Vocabulary;
+    use super::super::{from_str,parse_line_with,BinaryClassification,MultiLabelClassification,Regression};
+
+    #[test]
+    fn vw_rows() {
+        let vocab = Vocabulary::new();
+        let opts = ParseOptions::new().format(Format::Vw);
+        let row = parse_line_with(&BinaryClassification, &vocab, &opts, "1 2.5 'doc7 |user age:3 us |ad:2 id_9 price:1.5| a").unwrap();
+        assert!(row.y);
+        assert_eq!((row.weight, row.comment.as_deref()), (Some(2.5), Some("doc7")));
+        assert_eq!(vocab.names(), vec!["user^age", "user^us", "ad^id_9", "ad^price", "a"]);
+        assert_eq!(row.x.2, vec![3.0, 1.0, 2.0, 3.0, 1.0]);
+
+        let flat = ParseOptions::new().format(Format::Vw).namespaces(Namespaces::Flatten);
+        let row = parse_line_with(&Regression, &SparseData::new(8), &flat, "0.5 |f 3:2 5").unwrap();
+        assert_eq!((row.y, row.weight, row.x.1, row.x.2), (0.5, None, vec![3, 5], vec![2.0, 1.0]));
+
+        let bad = |s| parse_line_with(&Regression, &vocab, &opts, s).is_err();
+        assert!(bad("1 x |f a"));
+        assert!(bad("1 |f:y a"));
+        assert!(bad("1 1 1 1 |f a"));
+        assert!(bad("1 1 x |f a"));
+    }
+
+    #[test]
+    fn vw_tags() {
+        let vocab = Vocabulary::new();
+        let opts = ParseOptions::new().format(Format::Vw);
+        let tag = |s| {
+            let row = parse_line_with(&Regression, &vocab, &opts, s).unwrap();
+            (row.y, row.weight, row.comment)
+        };
+        assert_eq!(tag("1 zebra|f a"), (1.0, None, Some("zebra".into())));
+        assert_eq!(tag("1 2 0.5 zebra|f a"), (1.0, Some(2.0), Some("zebra".into())));
+        assert_eq!(tag("1 2 'zebra |f a"), (1.0, Some(2.0), Some("zebra".into())));
+        assert_eq!(tag("1 2 'zebra|f a"), (1.0, Some(2.0), Some("zebra".into())));
+        assert_eq!(tag("1 2 |f a"), (1.0, Some(2.0), None));
+        assert_eq!(tag("1 2"), (1.0, Some(2.0), None));
+    }
+
+    #[test]
+    fn vw_unlabeled() {
+        let opts = ParseOptions::new().format(Format::Vw);
+        assert!(parse_line_with(&Regression, &Vocabulary::new(), &opts, " |f a").is_err());
+        let row = parse_line_with(&MultiLabelClassification, &Vocabulary::new(), &opts, " |f a b").unwrap();
+        assert!(row.y.is_empty());
+        assert_eq!(row.x.1, vec![0, 1]);
+    }
+
+    #[test]
+    fn vw_reader() {
+        let data = "1 |f a b\n-1 'untagged|f c\n";
+        let vocab = Vocabulary::new();
+        let rows: Vec<_> = from_str(data, BinaryClassification, &vocab)
+            .options(ParseOptions::new().format(Format::Vw))
+            .map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].comment.as_deref(), Some("untagged"));
+        assert_eq!(rows[1].x.1, vec![2]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Writes rows back out in SVMlight/LIBSVM format
+use std::collections::HashSet;
+use std::fmt::{Display,Write as FmtWrite};
+use std::io::{self,Write};
+
+use hashing::HashedSparseData;
+use types::{DenseData,Sparse,SparseData};
+use super::{Row,Regression,BinaryClassification,DisjointClassification,
+            MultiLabelClassification,Tags};
+
+fn invalid(msg: &str, token: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {:?}", msg, token))
+}
+
+/// Counterpart to `TargetReader`: formats a target so it reads back unchanged
+pub trait TargetWriter {
+    type In;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()>;
+}
+
+impl <T: TargetWriter + ?Sized> TargetWriter for &T {
+    type In = T::In;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        (**self).write(y, out)
+    }
+}
+
+impl TargetWriter for Regression {
+    type In = f32;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        write!(out, "{}", y).unwrap();
+        Ok(())
+    }
+}
+
+impl TargetWriter for BinaryClassification {
+    type In = bool;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        out.push_str(if *y { "1" } else { "-1" });
+        Ok(())
+    }
+}
+
+impl TargetWriter for DisjointClassification {
+    type In = usize;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        write!(out, "{}", y).unwrap();
+        Ok(())
+    }
+}
+
+impl TargetWriter for MultiLabelClassification {
+    type In = HashSet<usize>;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        let mut classes: Vec<_> = y.iter().collect();
+        classes.sort();
+        for (i, c) in classes.into_iter().enumerate() {
+            if i > 0 { out.push(','); }
+            write!(out, "{}", c).unwrap();
+        }
+        Ok(())
+    }
+}
+
+impl TargetWriter for Tags {
+    type In = HashSet<String>;
+
+    fn write(&self, y: &Self::In, out: &mut String) -> io::Result<()> {
+        let mut tags: Vec<_> = y.iter().filter(|t| !t.is_empty()).collect();
+        tags.sort();
+        for (i, t) in tags.into_iter().enumerate() {
+            if t.contains(|c: char| c == ',' || c == '#' || c.is_whitespace()) {
+                return Err(invalid("tag cannot be written", t))
+            }
+            if i > 0 { out.push(','); }
+            out.push_str(t);
+        }
+        Ok(())
+    }
+}
+
+/// Counterpart to `DataParse`: formats the features of a row
+pub trait DataWrite {
+    type In;
+
+    fn write(&self, x: &Self::In, out: &mut String) -> io::Result<()>;
+}
+
+impl <D: DataWrite + ?Sized> DataWrite for &D {
+    type In = D::In;
+
+    fn write(&self, x: &Self::In, out: &mut String) -> io::Result<()> {
+        (**self).write(x, out)
+    }
+}
+
+impl DataWrite for DenseData {
+    type In = Vec<f32>;
+
+    fn write(&self, x: &Self::In, out: &mut String) -> io::Result<()> {
+        let offset = self.base().offset();
+        for (i, v) in x.iter().enumerate() {
+            write!(out, " {}:{}", i + offset, v).unwrap();
+        }
+        Ok(())
+    }
+}
+
+impl DataWrite for SparseData {
+    type In = Sparse;
+
+    fn write(&self, x: &Self::In, out: &mut String) -> io::Result<()> {
+        let offset = self.base().offset();
+        for (i, v) in x.1.iter().zip(x.2.iter()) {
+            write!(out, " {}:{}", i + offset, v).unwrap();
+        }
+        Ok(())
+    }
+}
+
+/// Writes bucket numbers as 0-based indices
+impl DataWrite for HashedSparseData {
+    type In = Sparse;
+
+    fn write(&self, x: &Self::In, out: &mut String) -> io::Result<()> {
+        for (i, v) in x.1.iter().zip(x.2.iter()) {
+            write!(out, " {}:{}", i, v).unwrap();
+        }
+        Ok(())
+    }
+}
+
+/// Writes rows, one per line
+pub struct Writer<TW: TargetWriter, DW: DataWrite, W: Write> {
+    w: W,
+    tw: TW,
+    dw: DW,
+    buf: String
+}
+
+impl <TW: TargetWriter, DW: DataWrite, W: Write> Writer<TW, DW, W> {
+    pub fn new(w: W, tw: TW, dw: DW) -> Self {
+        Writer { w, tw, dw, buf: String::new() }
+    }
+
+    /// Writes one row.  Meta entries are written as `key:value` tokens; keys
+    /// other than `sid` read back only when the reader's `ParseOptions`
+    /// list them with `meta_key`.  `qid` and `cost` are rejected as meta keys.
+    pub fn write_row<Q: Display>(&mut self, row: &Row<TW::In, DW::In, Q>) -> io::Result<()> {
+        self.buf.clear();
+        self.tw.write(&row.y, &mut self.buf)?;
+        // An empty target is marked by a leading space
+        if self.buf.is_empty() { self.buf.push(' '); }
+
+        if let Some(ref qid) = row.qid {
+            let start = self.buf.len();
+            write!(self.buf, " qid:{}", qid).unwrap();
+            let id = &self.buf[start + 5..];
+            if id.is_empty() || id.contains(|c: char| c == '#' || c.is_whitespace()) {
+                return Err(invalid("qid cannot be written", id))
+            }
+        }
+        if let Some(w) = row.weight {
+            write!(self.buf, " cost:{}", w).unwrap();
+        }
+        for (k, v) in row.meta.iter() {
+            let breaks = |c: char| c == '#' || c.is_whitespace();
+            if k.is_empty() || k == "qid" || k == "cost" || k.contains(':') || k.contains(breaks) || v.contains(breaks) {
+                return Err(invalid("meta token cannot be written", &format!("{}:{}", k, v)))
+            }
+            write!(self.buf, " {}:{}", k, v).unwrap();
+        }
+        self.dw.write(&row.x, &mut self.buf)?;
+        if let Some(ref c) = row.comment {
+            if c.contains('\n') {
+                return Err(invalid("comment cannot span lines", c))
+            }
+            write!(self.buf, " #{}", c).unwrap();
+        }
+        self.buf.push('\n');
+        self.w.write_all(self.buf.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{from_str,Meta};
+    use options::ParseOptions;
+    use types::IndexBase;
+
+    #[test]
+    fn write_sparse() {
+        let sd = SparseData::new(10);
+        let mut w = Writer::new(Vec::new(), &Regression, &sd);
+        w.write_row(&Row::new(0.1, Sparse(10, vec![1, 7], vec![-2.5, 1e-8]), Some(3), Some(" hi".into()))).unwrap();
+        let mut meta = Meta::new();
+        meta.insert("sid", "4");
+        w.write_row(&Row::new(2.0, Sparse(10, vec![], vec![]), None, None).with_weight(Some(0.5)).with_meta(meta)).unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out, "0.1 qid:3 1:-2.5 7:0.00000001 # hi\n2 cost:0.5 sid:4\n");
+
+        let rows: Vec<_> = from_str(&out, &Regression, &sd).map(|r| r.unwrap()).collect();
+        assert_eq!(rows[0].x.1, vec![1, 7]);
+        assert_eq!(rows[0].x.2, vec![-2.5, 1e-8]);
+        assert_eq!(rows[0].comment, Some(" hi".into()));
+        assert!(rows[1].x.1.is_empty());
+        assert_eq!(rows[1].weight, Some(0.5));
+        assert_eq!(rows[1].meta.get("sid"), Some("4"));
+    }
+
+    #[test]
+    fn write_custom_meta() {
+        let mut w = Writer::new(Vec::new(), &Regression, DenseData::new());
+        let mut meta = Meta::new();
+        meta.insert("doc", "a");
+        w.write_row(&Row::new(1.0, vec![2.0], None, None).with_meta(meta)).unwrap();
+        for key in &["qid", "cost"] {
+            let mut meta = Meta::new();
+            meta.insert(*key, "x");
+            assert!(w.write_row(&Row::new(1.0, vec![], None, None).with_meta(meta)).is_err());
+        }
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out, "1 doc:a 0:2\n");
+
+        assert!(from_str(&out, &Regression, DenseData::new()).next().unwrap().is_err());
+        let row = from_str(&out, &Regression, DenseData::new())
+            .options(ParseOptions::new().meta_key("doc"))
+            .next().unwrap().unwrap();
+        assert_eq!((row.meta.get("doc"), row.x), (Some("a"), vec![2.0]));
+    }
+
+    #[test]
+    fn write_empty_labels() {
+        let mut w = Writer::new(Vec::new(), &MultiLabelClassification, DenseData::new());
+        w.write_row(&Row::new(HashSet::new(), vec![1.0, 0.5], None, None)).unwrap();
+        w.write_row(&Row::new([3, 1].iter().cloned().collect(), vec![0.0], None, None)).unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out, "  0:1 1:0.5\n1,3 0:0\n");
+
+        let ys: Vec<_> = from_str(&out, &MultiLabelClassification, DenseData::new())
+            .map(|r| r.unwrap().y.len()).collect();
+        assert_eq!(ys, vec![0, 2]);
+    }
+
+    #[test]
+    fn reject_bad_tag() {
+        let mut w = Writer::new(Vec::new(), &Tags, DenseData::new());
+        let y = ["a b".to_owned()].iter().cloned().collect();
+        assert!(w.write_row(&Row::new(y, vec![], None, None)).is_err());
+    }
+
+    #[test]
+    fn write_string_qid() {
+        let mut w = Writer::new(Vec::new(), &Regression, DenseData::new());
+        let row = Row::new(1.0, vec![2.0], None, None);
+        w.write_row(&row.with_qid(Some("q7"))).unwrap();
+        assert!(w.write_row(&Row::new(1.0, vec![], None, None).with_qid(Some("a b"))).is_err());
+        assert_eq!(w.into_inner(), b"1 qid:q7 0:2\n");
+    }
+
+    #[test]
+    fn write_one_based() {
+        let sd = SparseData::new(3).index_base(IndexBase::One);
+        let mut w = Writer::new(Vec::new(), &BinaryClassification, &sd);
+        w.write_row(&Row::new(true, Sparse(3, vec![0, 2], vec![1.0, 2.0]), None, None)).unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out, "1 1:1 3:2\n");
+
+        let row = from_str(&out, &BinaryClassification, &sd).next().unwrap().unwrap();
+        assert_eq!(row.x.1, vec![0, 2]);
+    }
+}